@@ -1,61 +1,2880 @@
+use std::collections::HashSet;
 use std::convert::TryFrom;
+use std::fmt;
 use std::fs;
+use std::io::{self, BufRead, BufWriter, Read, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::commands::{Encode, Decode, Remove, Print};
-use crate::png::Png;
-use crate::chunk::Chunk;
-use crate::Result;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use log::{info, warn, LevelFilter};
+use regex::{Regex, RegexBuilder};
+use serde_json::json;
+use sha2::{Digest, Sha256};
 
-pub fn encode(e: Encode) -> Result<()> {
-    let img_data = fs::read(&e.file_path);
-    match img_data {
-        Ok(img) => {
-            let mut png = Png::try_from(img.as_slice())?;
-            png.append_chunk(Chunk::new(e.chunk_type, e.message.as_bytes().to_vec()));
-            fs::write(e.file_path, png.as_bytes())?;
+use crate::color;
+use crate::commands::{Encode, Decode, Remove, Print, List, Search, Repl, CopyChunk, Merge, Diff, Capacity, Info, Dedupe, Strip, Verify, Validate, RenameChunk, Swap, ScanTrailing, EmbedFile, ExtractFile, Convert, Hash, Undo, Canonicalize};
+use png_message::png::{Png, PngParseOptions};
+use png_message::render;
+use png_message::chunk::Chunk;
+use png_message::chunk_type::ChunkType;
+use png_message::diff;
+use png_message::embed;
+use png_message::error::PngError;
+use png_message::ihdr::Ihdr;
+use png_message::split;
+use png_message::text::{InternationalTextChunk, TextChunk};
+use png_message::validate;
+use png_message::Result;
+
+pub fn encode(e: Encode, progress: &Progress) -> Result<()> {
+    check_chunk_type(&e.chunk_type, e.force)?;
+    if let Some(warning) = public_type_warning(&e.chunk_type) {
+        if !e.force {
+            warn!("{}", warning);
+        }
+    }
+
+    if e.recursive {
+        let done = match &e.manifest {
+            Some(manifest) => read_manifest(manifest)?,
+            None => HashSet::new(),
+        };
+        // held while appending so lines from different threads don't interleave
+        let manifest_lock = Mutex::new(());
+        return for_each_png(&e.file_path, "encoded", e.threads.unwrap_or(1), progress, |path| {
+            if done.contains(path) {
+                info!("Skipping {}, the manifest lists it as done", path.display());
+                return Ok(());
+            }
+            encode_file(&e, path)?;
+            match &e.manifest {
+                Some(manifest) => {
+                    let _guard = manifest_lock.lock().map_err(|_| "Manifest lock poisoned")?;
+                    record_in_manifest(manifest, path)
+                }
+                None => Ok(()),
+            }
+        });
+    }
+    encode_file(&e, &e.file_path)
+}
+
+fn encode_file(e: &Encode, file_path: &Path) -> Result<()> {
+    let destination = destination(file_path, &e.output, e.in_place)?;
+    check_writable(destination)?;
+    let mut png = open_png(file_path)?;
+    let mut message = match &e.from_file {
+        Some(path) => fs::read(path)?,
+        None => e.message.clone().unwrap_or_default().into_bytes(),
+    };
+    if e.normalize_newlines {
+        message = normalize_newlines(&message);
+    }
+    if e.force_ascii {
+        message = force_ascii(&message);
+    }
+    if let Some(name) = &e.encoding {
+        message = encode_text(std::str::from_utf8(&message)?, find_encoding(name)?);
+    }
+    let mut chunks = if let Some(keyword) = &e.itext {
+        if e.chunk_type.to_string() != "iTXt" {
+            return Err("The message is stored as an iTXt chunk, use iTXt as the chunk type".into());
+        }
+        if e.split.is_some() {
+            return Err("--split cannot be combined with --itext".into());
+        }
+        let language = e.language.as_deref().unwrap_or_default();
+        let text = InternationalTextChunk::new(keyword, language, "", std::str::from_utf8(&message)?)?;
+        vec![text.to_chunk(e.ztext)?]
+    } else if let Some(keyword) = &e.text {
+        let expected = if e.ztext { "zTXt" } else { "tEXt" };
+        if e.chunk_type.to_string() != expected {
+            return Err(format!("The message is stored as a {} chunk, use {} as the chunk type", expected, expected).into());
+        }
+        if e.split.is_some() {
+            return Err("--split cannot be combined with --text".into());
+        }
+        let text = TextChunk::new(keyword, std::str::from_utf8(&message)?)?;
+        vec![if e.ztext { text.to_compressed_chunk()? } else { text.to_chunk()? }]
+    } else if e.ztext {
+        return Err("--ztext requires --text or --itext".into());
+    } else if let Some(size) = e.split {
+        split::split_message(e.chunk_type, &message, size)?
+    } else {
+        vec![Chunk::new(e.chunk_type, message)]
+    };
+    // measured after compression and splitting, so it reflects what is actually embedded
+    if let Some(max_size) = e.max_size {
+        let size: usize = chunks.iter().map(|chunk| chunk.data().len()).sum();
+        if size > max_size {
+            return Err(format!("Encoded message is {} bytes, more than the maximum of {}", size, max_size).into());
         }
-        Err(e) => eprintln!("Error: {}", e),
     }
+    let chunk_type = e.chunk_type.to_string();
+    if e.if_absent {
+        let present = chunks.iter().all(|chunk| {
+            png.chunks()
+                .iter()
+                .any(|other| other.chunk_type() == chunk.chunk_type() && other.data() == chunk.data())
+        });
+        // status lines go to stderr so they never end up inside a PNG written to stdout with '-o -'
+        if present {
+            eprintln!("The message is already present in {}", file_path.display());
+            return Ok(());
+        }
+    }
+    let existing = if e.replace { png.chunk_by_type_mut(&chunk_type) } else { None };
+    if let Some(existing) = existing {
+        // --replace conflicts with --split, so there is a single chunk. The CRC follows the new data
+        existing.set_data(chunks.remove(0).data().to_vec());
+        eprintln!("Replaced the existing {} chunk", chunk_type);
+    } else {
+        if e.replace {
+            eprintln!("Added a new {} chunk", chunk_type);
+        }
+        match &e.after {
+            Some(anchor) => {
+                let index = png
+                    .chunks()
+                    .iter()
+                    .position(|chunk| chunk.chunk_type() == anchor)
+                    .ok_or_else(|| format!("Could not find a {} chunk to insert after", anchor))?;
+                for (offset, chunk) in chunks.into_iter().enumerate() {
+                    png.insert_chunk(index + 1 + offset, chunk)?;
+                }
+            }
+            None => {
+                for chunk in chunks {
+                    png.append_chunk(chunk);
+                }
+            }
+        }
+    }
+    if e.tag {
+        png.append_chunk(tag_chunk()?);
+    }
+    write_png(destination, &png, e.backup, e.force)?;
     Ok(())
 }
 
-pub fn decode(d: Decode) -> Result<()> {
-    let img_data = fs::read(&d.file_path);
-    match img_data {
-        Ok(img) => {
-            let png = Png::try_from(img.as_slice())?;
-            let chunk = png.chunk_by_type(&d.chunk_type.to_string());
-            println!("Hidden message: {}", chunk.unwrap().data_as_string()?);
-        },
-        Err(e) => { eprintln!("Error: {}", e)}
+pub fn decode(d: Decode, out: &mut impl Write) -> Result<()> {
+    let img = read_input(&d.file_path)?;
+    let options = PngParseOptions {
+        verify_crc: !d.ignore_crc,
+        chunks_after_iend: d.after_iend,
+    };
+    let png = check_trailing(parse_png_with(&img, options)?, d.strict)?;
+    if d.show_tag {
+        match png.chunk_by_type(TAG_CHUNK_TYPE) {
+            Some(tag) => writeln!(out, "Tag: {}", String::from_utf8_lossy(tag.data()))?,
+            None => writeln!(out, "Tag: none")?,
+        }
+    }
+    let chunk_type = match (&d.type_regex, &d.chunk_type) {
+        (Some(regex), _) => png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .find(|chunk_type| regex.is_match(chunk_type))
+            .ok_or(PngError::ChunkNotFound)?,
+        (None, Some(chunk_type)) => resolve_chunk_type(&png, chunk_type, d.ignore_case),
+        (None, None) => return Err("Pass a CHUNK_TYPE or --type-regex".into()),
+    };
+    let end = if d.no_newline { "" } else { "\n" };
+    if d.itext {
+        let chunks = png.chunks_by_type(&chunk_type);
+        if chunks.is_empty() {
+            return Err(PngError::ChunkNotFound.into());
+        }
+        let texts = chunks
+            .into_iter()
+            .map(InternationalTextChunk::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        let count = if d.all { texts.len() } else { d.count };
+        for text in texts.into_iter().take(count) {
+            write!(
+                out,
+                "{} [{}] ({}): {}{}",
+                text.keyword, text.language, text.translated_keyword, text.text, end
+            )?;
+        }
+        return Ok(());
+    }
+    if d.text {
+        let chunks = png.chunks_by_type(&chunk_type);
+        if chunks.is_empty() {
+            return Err(PngError::ChunkNotFound.into());
+        }
+        let mut texts = chunks.into_iter().map(TextChunk::try_from).collect::<Result<Vec<_>>>()?;
+        if let Some(keyword) = &d.keyword {
+            let keywords: Vec<String> = texts.iter().map(|text| text.keyword.clone()).collect();
+            texts.retain(|text| &text.keyword == keyword);
+            if texts.is_empty() {
+                return Err(format!("No {} chunk has keyword {}, found: {}", chunk_type, keyword, keywords.join(", ")).into());
+            }
+        }
+        let count = if d.all { texts.len() } else { d.count };
+        for text in texts.into_iter().take(count) {
+            write!(out, "{}: {}{}", text.keyword, text.text, end)?;
+        }
+        return Ok(());
+    }
+
+    let messages = hidden_messages(&png, &chunk_type)?;
+    let encoding = d.encoding.as_deref().map(find_encoding).transpose()?;
+    let count = if d.all { messages.len() } else { d.count };
+    let messages: Vec<Vec<u8>> = messages.into_iter().take(count).collect();
+    if let Some(tee) = &d.tee {
+        fs::write(tee, messages.concat())?;
+    }
+    for message in messages {
+        if d.raw {
+            out.write_all(&message)?;
+        } else if message.is_empty() {
+            write!(out, "(chunk exists but contains no data){}", end)?;
+        } else if let Some(encoding) = encoding {
+            write!(out, "Hidden message: {}{}", encoding.decode_without_bom_handling(&message).0, end)?;
+        } else {
+            write!(out, "Hidden message: {}{}", render::render(&message, d.format), end)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn remove(r: Remove, out: &mut impl Write) -> Result<()> {
+    if r.dry_run {
+        let mut png = open_png(&r.file_path)?;
+        let chunk_type = resolve_chunk_type(&png, &r.chunk_type, r.ignore_case);
+        return print_planned(out, &[png.remove_chunk(&chunk_type)?]);
+    }
+
+    let destination = destination(&r.file_path, &r.output, r.in_place)?;
+    check_writable(destination)?;
+    let mut png = open_png(&r.file_path)?;
+    let chunk_type = resolve_chunk_type(&png, &r.chunk_type, r.ignore_case);
+    let removed = png.remove_chunk(&chunk_type)?;
+    info!("Removed chunk:\n{}", removed);
+    write_png(destination, &png, r.backup, r.force)?;
+    Ok(())
+}
+
+// with 'verbose' parsing progress is written to 'progress', one line per chunk
+pub fn print(p: Print, out: &mut impl Write, progress: &Progress) -> Result<()> {
+    let img = read_input(&p.file_path)?;
+    let options = PngParseOptions {
+        verify_crc: !p.ignore_crc,
+        ..PngParseOptions::default()
+    };
+    // the chunk count isn't known until the end, so the bytes show how far along it is
+    let mut chunks = 0;
+    let (png, warnings) = Png::try_from_opts_with_progress(img.as_slice(), options, |parsed, total| {
+        chunks += 1;
+        progress.report(format_args!("Parsed {} chunk(s), {}/{} bytes", chunks, parsed, total));
+    })?;
+    for warning in warnings {
+        warn!("{}", warning);
+    }
+    let png = check_trailing(png, p.strict)?;
+    let color = p.color.enabled();
+    for (chunk, offset) in png.chunks().iter().zip(png.chunk_offsets()) {
+        if p.exclude.contains(chunk.chunk_type()) || !matches_type_regex(chunk, &p.type_regex) {
+            continue;
+        }
+        if p.offsets {
+            writeln!(out, "Offset: {}", offset)?;
+        }
+        write!(out, "{}", color::format_chunk(chunk, color))?;
+        if p.chars {
+            if let Some(count) = chunk.data_char_count() {
+                writeln!(out, "Characters: {}", count)?;
+            }
+        }
+        if let Some(preview) = p.preview {
+            let data = &chunk.data()[..preview.min(chunk.data().len())];
+            writeln!(out, "Preview: {}", render::render(data, render::Format::Escape))?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+// one line per chunk: index, type and data length
+pub fn list(l: List, out: &mut impl Write) -> Result<()> {
+    let img = read_input(&l.file_path)?;
+    let png = check_trailing(parse_png(&img, l.ignore_crc)?, l.strict)?;
+    for (index, (chunk, offset)) in png.chunks().iter().zip(png.chunk_offsets()).enumerate() {
+        // indexes still count excluded chunks so they can be passed to swap
+        if l.exclude.contains(chunk.chunk_type())
+            || !in_length_range(chunk, l.min_length, l.max_length)
+            || !matches_type_regex(chunk, &l.type_regex)
+        {
+            continue;
+        }
+        let mut line = format!("{:>4} {} {:>10}", index, chunk.chunk_type(), chunk.length());
+        if l.show_offset {
+            line.push_str(&format!(" {:>10}", offset));
+        }
+        if l.show_crc {
+            line.push_str(&format!(" {:08x}", chunk.crc()));
+        }
+        writeln!(out, "{}", line)?;
+    }
+
+    Ok(())
+}
+
+// returns how many chunks contain the query
+pub fn search(s: Search, out: &mut impl Write) -> Result<usize> {
+    let img = read_input(&s.file_path)?;
+    let png = check_trailing(parse_png(&img, s.ignore_crc)?, s.strict)?;
+    let type_regex = match &s.type_regex {
+        Some(regex) if s.ignore_case => Some(RegexBuilder::new(regex.as_str()).case_insensitive(true).build()?),
+        type_regex => type_regex.clone(),
+    };
+    // each match with its position and length, which ignoring case may differ from the query's
+    let mut matches: Vec<(&Chunk, usize, usize)> = if s.ignore_case && !s.bytes {
+        let pattern = regex::bytes::RegexBuilder::new(&regex::escape(&s.query))
+            .case_insensitive(true)
+            .build()?;
+        png.chunks()
+            .iter()
+            .filter_map(|chunk| pattern.find(chunk.data()).map(|found| (chunk, found.start(), found.len())))
+            .filter(|&(_, _, len)| len > 0)
+            .collect()
+    } else {
+        let needle = if s.bytes {
+            parse_hex(&s.query)?
+        } else {
+            s.query.as_bytes().to_vec()
+        };
+        png.search(&needle)
+            .into_iter()
+            .map(|(chunk, position)| (chunk, position, needle.len()))
+            .collect()
+    };
+    matches.retain(|(chunk, _, _)| {
+        in_length_range(chunk, s.min_length, s.max_length) && matches_type_regex(chunk, &type_regex)
+    });
+    if s.count_only {
+        writeln!(out, "{}", matches.len())?;
+        return Ok(matches.len());
+    }
+    for (chunk, position, len) in &matches {
+        writeln!(out, "{}: {}", chunk.chunk_type(), snippet(chunk.data(), *position, *len))?;
+    }
+
+    Ok(matches.len())
+}
+
+// save follows the same --output/--in-place and --backup rules as the other commands
+fn save_repl<'a>(r: &'a Repl, png: &Png) -> Result<&'a Path> {
+    let destination = destination(&r.file_path, &r.output, r.in_place)?;
+    check_writable(destination)?;
+    write_png(destination, png, r.backup, r.force)?;
+    Ok(destination)
+}
+
+pub fn repl(r: Repl, input: impl BufRead, out: &mut impl Write) -> Result<()> {
+    let img = read_input(&r.file_path)?;
+    let mut png = Png::try_from(img.as_slice())?;
+    write!(out, "> ")?;
+    out.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("list"), None) => {
+                for chunk in png.chunks() {
+                    writeln!(out, "{}", chunk.chunk_type())?;
+                }
+            }
+            (Some("print"), Some(chunk_type)) => match png.chunk_by_type(chunk_type) {
+                Some(chunk) => writeln!(out, "{}", chunk)?,
+                None => writeln!(out, "Error: Could not find chunk")?,
+            },
+            (Some("decode"), Some(chunk_type)) => match hidden_messages(&png, chunk_type) {
+                Ok(messages) => writeln!(out, "Hidden message: {}", String::from_utf8_lossy(&messages[0]))?,
+                Err(e) => writeln!(out, "Error: {}", e)?,
+            },
+            (Some("remove"), Some(chunk_type)) => match png.remove_chunk(chunk_type) {
+                Ok(chunk) => writeln!(out, "Removed {}", chunk.chunk_type())?,
+                Err(e) => writeln!(out, "Error: {}", e)?,
+            },
+            (Some("save"), None) => match save_repl(&r, &png) {
+                Ok(destination) => writeln!(out, "Saved {}", destination.display())?,
+                Err(e) => writeln!(out, "Error: {}", e)?,
+            },
+            (Some("quit"), None) | (Some("exit"), None) => break,
+            (None, _) => {}
+            _ => writeln!(out, "Commands: list, print <type>, decode <type>, remove <type>, save, quit")?,
+        }
+        write!(out, "> ")?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+pub fn copy_chunk(c: CopyChunk) -> Result<()> {
+    let output = destination(&c.destination_path, &c.output, c.in_place)?;
+    check_writable(output)?;
+    let source = Png::try_from(read_input(&c.source_path)?.as_slice())?;
+    let chunk = source
+        .chunk_by_type(&c.chunk_type.to_string())
+        .ok_or_else(|| format!("{} has no {} chunk", c.source_path.display(), c.chunk_type))?;
+
+    let mut destination = open_png(&c.destination_path)?;
+    destination.insert_before_end(chunk.clone());
+    write_png(output, &destination, c.backup, c.force)?;
+
+    Ok(())
+}
+
+pub fn merge(m: Merge) -> Result<()> {
+    let output = destination(&m.destination_path, &m.output, m.in_place)?;
+    check_writable(output)?;
+    let mut destination = open_png(&m.destination_path)?;
+
+    let mut merged = 0;
+    for source_path in &m.source_paths {
+        let source = Png::try_from(read_input(source_path)?.as_slice())?;
+        // critical chunks belong to the source image, copying them would corrupt the destination
+        let chunks = source.chunks().iter().filter(|chunk| {
+            chunk.chunk_type().is_ancillary() && (m.types.is_empty() || m.types.contains(chunk.chunk_type()))
+        });
+        for chunk in chunks {
+            destination.insert_before_end(chunk.clone());
+            merged += 1;
+        }
+    }
+    write_png(output, &destination, m.backup, m.force)?;
+    // on stderr, stdout may be carrying the PNG
+    eprintln!("Merged {} chunk(s)", merged);
+
+    Ok(())
+}
+
+pub fn diff(d: Diff, out: &mut impl Write) -> Result<()> {
+    let original = check_trailing(parse_png(&read_input(&d.original_path)?, d.ignore_crc)?, d.strict)?;
+    let other = check_trailing(parse_png(&read_input(&d.other_path)?, d.ignore_crc)?, d.strict)?;
+
+    for (index, chunk_diff) in diff::diff_chunks(original.chunks(), other.chunks()).iter().enumerate() {
+        writeln!(out, "{:>4} {}", index, chunk_diff)?;
+    }
+
+    Ok(())
+}
+
+pub fn capacity(c: Capacity) -> Result<()> {
+    let img = read_input(&c.file_path)?;
+    let png = check_trailing(parse_png(&img, c.ignore_crc)?, c.strict)?;
+    let ihdr = read_ihdr(&png)?;
+
+    println!("{}x{} pixels, {} channels", ihdr.width, ihdr.height, ihdr.channels());
+    for bits in [1, 2, 4].iter() {
+        println!("{} bit(s) per channel: {} bytes", bits, ihdr.lsb_capacity(*bits)?);
+    }
+
+    Ok(())
+}
+
+pub fn info(i: Info, out: &mut impl Write) -> Result<()> {
+    let img = read_input(&i.file_path)?;
+    let png = check_trailing(parse_png(&img, i.ignore_crc)?, i.strict)?;
+    let ihdr = read_ihdr(&png)?;
+
+    if i.json {
+        // null when LSB embedding isn't supported for the image, e.g. indexed color
+        let lsb_capacity = ihdr.lsb_capacity(1).ok();
+        let summary = json!({
+            "width": ihdr.width,
+            "height": ihdr.height,
+            "bit_depth": ihdr.bit_depth,
+            "color_type": ihdr.color_type,
+            "interlace": ihdr.interlace,
+            "chunk_count": png.chunk_count(),
+            "lsb_capacity_bytes": lsb_capacity,
+        });
+        write_json(out, &summary, i.pretty)?;
+        return Ok(());
+    }
+
+    writeln!(out, "Width: {}", ihdr.width)?;
+    writeln!(out, "Height: {}", ihdr.height)?;
+    writeln!(out, "Bit depth: {}", ihdr.bit_depth)?;
+    writeln!(out, "Color type: {} ({})", ihdr.color_type, ihdr.color_type_name())?;
+    writeln!(out, "Compression: {}", ihdr.compression)?;
+    writeln!(out, "Filter: {}", ihdr.filter)?;
+    writeln!(out, "Interlace: {}", if ihdr.interlace == 1 { "Adam7" } else { "none" })?;
+
+    Ok(())
+}
+
+pub fn dedupe(d: Dedupe) -> Result<()> {
+    let destination = destination(&d.file_path, &d.output, d.in_place)?;
+    check_writable(destination)?;
+    let mut png = open_png(&d.file_path)?;
+    let removed = png.dedupe();
+    // an unchanged file is only left alone in place, --output always gets a PNG
+    if removed > 0 || !d.in_place {
+        write_png(destination, &png, d.backup, d.force)?;
+    }
+    // on stderr, stdout may be carrying the PNG
+    eprintln!("Removed {} duplicate chunk(s)", removed);
+
+    Ok(())
+}
+
+pub fn canonicalize(c: Canonicalize) -> Result<()> {
+    let destination = destination(&c.file_path, &c.output, c.in_place)?;
+    check_writable(destination)?;
+    let mut png = open_png(&c.file_path)?;
+    let moved = png.canonicalize();
+    // an unchanged file is only left alone in place, --output always gets a PNG
+    if moved > 0 || !c.in_place {
+        write_png(destination, &png, c.backup, c.force)?;
+    }
+    // on stderr, stdout may be carrying the PNG
+    eprintln!("Moved {} chunk(s)", moved);
+
+    Ok(())
+}
+
+pub fn strip(s: Strip, out: &mut (impl Write + Send), progress: &Progress) -> Result<()> {
+    if let Some(critical) = s.exclude.iter().find(|chunk_type| chunk_type.is_critical()) {
+        return Err(format!("{} is a critical chunk type, stripping it would corrupt the image", critical).into());
+    }
+
+    if s.recursive {
+        let out = Mutex::new(out);
+        return for_each_png(&s.file_path, "stripped", s.threads.unwrap_or(1), progress, |path| {
+            // buffered so the lines of one file aren't interleaved with another's
+            let mut lines = Vec::new();
+            strip_file(&s, path, &mut lines)?;
+            let mut out = out.lock().map_err(|_| "Output lock poisoned")?;
+            for line in String::from_utf8_lossy(&lines).lines() {
+                writeln!(out, "{}: {}", path.display(), line)?;
+            }
+            Ok(())
+        });
+    }
+    strip_file(&s, &s.file_path, out)
+}
+
+fn strip_file(s: &Strip, file_path: &Path, out: &mut impl Write) -> Result<()> {
+    // excluded types are stripped even if --keep lists them
+    let keep: Vec<ChunkType> = s.keep.iter().filter(|&chunk_type| !s.exclude.contains(chunk_type)).copied().collect();
+    if s.dry_run {
+        return print_planned(out, &open_png(file_path)?.strip(&keep));
+    }
+
+    let destination = destination(file_path, &s.output, s.in_place)?;
+    check_writable(destination)?;
+    let mut png = open_png(file_path)?;
+    let removed = png.strip(&keep);
+    // an unchanged file is only left alone in place, --output always gets a PNG
+    if !removed.is_empty() || !s.in_place {
+        write_png(destination, &png, s.backup, s.force)?;
+    }
+    if is_stdio(destination) {
+        // stdout is carrying the PNG
+        eprintln!("Removed {} ancillary chunk(s)", removed.len());
+    } else {
+        writeln!(out, "Removed {} ancillary chunk(s)", removed.len())?;
+    }
+
+    Ok(())
+}
+
+// whether the chunk's data length is within the --min-length/--max-length bounds, both inclusive
+fn in_length_range(chunk: &Chunk, min: Option<usize>, max: Option<usize>) -> bool {
+    let length = chunk.data().len();
+    min.is_none_or(|min| length >= min) && max.is_none_or(|max| length <= max)
+}
+
+// true when there is no --type-regex
+fn matches_type_regex(chunk: &Chunk, regex: &Option<Regex>) -> bool {
+    regex
+        .as_ref()
+        .is_none_or(|regex| regex.is_match(&chunk.chunk_type().to_string()))
+}
+
+// --json output is a single line unless 'pretty' asks for it indented
+fn write_json(out: &mut impl Write, value: &serde_json::Value, pretty: bool) -> Result<()> {
+    if pretty {
+        writeln!(out, "{}", serde_json::to_string_pretty(value)?)?;
+    } else {
+        writeln!(out, "{}", value)?;
+    }
+    Ok(())
+}
+
+// what a --dry-run of a removing command would have removed
+fn print_planned(out: &mut impl Write, removed: &[Chunk]) -> Result<()> {
+    let types: Vec<String> = removed.iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+    writeln!(out, "Would remove {} chunk(s): {}", removed.len(), types.join(", "))?;
+    Ok(())
+}
+
+pub fn verify(v: Verify, out: &mut impl Write) -> Result<()> {
+    // parsed leniently, a strict parse would fail on the very chunk being inspected
+    let img = read_input(&v.file_path)?;
+    let png = parse_png(&img, true)?;
+    let chunk = png.chunk_by_type(&v.chunk_type.to_string()).ok_or(PngError::ChunkNotFound)?;
+
+    writeln!(out, "Stored CRC:   {:08x}", chunk.crc())?;
+    writeln!(out, "Computed CRC: {:08x}", chunk.computed_crc())?;
+    if chunk.has_valid_crc() {
+        writeln!(out, "CRC matches")?;
+        Ok(())
+    } else {
+        writeln!(out, "CRC mismatch")?;
+        Err(PngError::InvalidChunk.into())
+    }
+}
+
+pub fn validate(v: Validate, out: &mut impl Write) -> Result<()> {
+    let failures = validate::validate(&read_input(&v.file_path)?);
+
+    if v.json {
+        let failures_json: Vec<_> = failures
+            .iter()
+            .map(|failure| json!({ "kind": failure.kind, "detail": failure.detail }))
+            .collect();
+        write_json(out, &json!({ "valid": failures.is_empty(), "failures": failures_json }), v.pretty)?;
+    } else if failures.is_empty() {
+        writeln!(out, "Valid")?;
+    } else {
+        for failure in &failures {
+            writeln!(out, "{}: {}", failure.kind, failure.detail)?;
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} validation failure(s)", failures.len()).into())
+    }
+}
+
+pub fn rename_chunk(r: RenameChunk) -> Result<()> {
+    check_chunk_type(&r.new_type, r.force)?;
+    let destination = destination(&r.file_path, &r.output, r.in_place)?;
+    check_writable(destination)?;
+
+    let mut png = open_png(&r.file_path)?;
+    let chunk = png
+        .chunk_by_type_mut(&r.old_type.to_string())
+        .ok_or(PngError::ChunkNotFound)?;
+    // the CRC covers the type, it is recomputed from the new type when written
+    chunk.set_chunk_type(r.new_type);
+    write_png(destination, &png, r.backup, r.force)?;
+
+    Ok(())
+}
+
+pub fn swap(s: Swap) -> Result<()> {
+    let destination = destination(&s.file_path, &s.output, s.in_place)?;
+    check_writable(destination)?;
+    let mut png = open_png(&s.file_path)?;
+    png.swap_chunks(s.first, s.second)?;
+
+    // readers expect IHDR first and stop at IEND, moving either breaks the image
+    let chunks = png.chunks();
+    let ihdr_moved = chunks.first().is_some_and(|chunk| chunk.chunk_type().to_string() != "IHDR");
+    let iend_moved = chunks.last().is_some_and(|chunk| chunk.chunk_type().to_string() != "IEND");
+    if (ihdr_moved || iend_moved) && !s.force {
+        return Err("This swap moves IHDR off the front or IEND off the end, pass --force to do it anyway".into());
     }
+    write_png(destination, &png, s.backup, s.force)?;
+
     Ok(())
 }
 
-pub fn remove(r: Remove) -> Result<()> {
-    let img_data = fs::read(&r.file_path);
-    match img_data {
-        Ok(img) => {
-            let mut png = Png::try_from(img.as_slice())?;
-            png.remove_chunk(&r.chunk_type.to_string())?;
-            fs::write(r.file_path, png.as_bytes())?;
+// everything after IEND, chunks included, since viewers stop reading there
+pub fn scan_trailing(s: ScanTrailing, out: &mut impl Write) -> Result<()> {
+    let img = read_input(&s.file_path)?;
+    let png = parse_png(&img, s.ignore_crc)?;
+    let iend_end = png
+        .chunks()
+        .iter()
+        .zip(png.chunk_offsets())
+        .find(|(chunk, _)| chunk.chunk_type().to_string() == "IEND")
+        .map(|(chunk, offset)| offset + chunk.total_size())
+        .ok_or("Could not find IEND chunk")?;
+    let trailing = &img[iend_end..];
+
+    match s.to_file {
+        Some(path) => {
+            fs::write(&path, trailing)?;
+            writeln!(out, "Wrote {} bytes after IEND to {}", trailing.len(), path.display())?;
+        }
+        None if trailing.is_empty() => writeln!(out, "No data after IEND")?,
+        None => {
+            writeln!(out, "{} bytes after IEND:", trailing.len())?;
+            writeln!(out, "{}", render::render(trailing, render::Format::Escape))?;
         }
-        Err(e) => eprintln!("Error: {}", e),
     }
+
+    Ok(())
+}
+
+pub fn embed_file(e: EmbedFile) -> Result<()> {
+    check_chunk_type(&e.chunk_type, e.force)?;
+    let destination = destination(&e.file_path, &e.output, e.in_place)?;
+    check_writable(destination)?;
+
+    let contents = fs::read(&e.input_path)?;
+    let name = e
+        .input_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("Input file name is not valid UTF-8")?;
+    let mut png = open_png(&e.file_path)?;
+    for chunk in embed::embed_file(e.chunk_type, name, &contents, e.chunk_size)? {
+        png.insert_before_end(chunk);
+    }
+    write_png(destination, &png, e.backup, e.force)?;
+
+    Ok(())
+}
+
+pub fn extract_file(e: ExtractFile) -> Result<()> {
+    let img = read_input(&e.file_path)?;
+    let png = check_trailing(parse_png(&img, e.ignore_crc)?, e.strict)?;
+    let (name, contents) = embed::extract_file(&png.chunks_by_type(&e.chunk_type.to_string()))?;
+
+    // only the file name is used so a crafted header can't write outside the current directory
+    let output = match e.output {
+        Some(output) => output,
+        None => PathBuf::from(Path::new(&name).file_name().ok_or("Embedded file has no name")?),
+    };
+    fs::write(&output, &contents)?;
+    println!("Extracted {} ({} bytes)", output.display(), contents.len());
+
     Ok(())
 }
 
-pub fn print(p: Print) -> Result<()> {
-    let img_data = fs::read(&p.file_path);
-    match img_data {
-        Ok(img) => {
-            let png = Png::try_from(img.as_slice())?;
-            for chunk in png.chunks() {
-                println!("{}", chunk);
+// moves a message between a raw chunk and a tEXt/zTXt chunk, keeping its position in the file
+pub fn convert(c: Convert) -> Result<()> {
+    check_chunk_type(&c.new_type, c.force)?;
+    let destination = destination(&c.file_path, &c.output, c.in_place)?;
+    check_writable(destination)?;
+
+    let mut png = open_png(&c.file_path)?;
+    let source_type = c.chunk_type.to_string();
+    let index = png
+        .chunks()
+        .iter()
+        .position(|chunk| chunk.chunk_type().to_string() == source_type)
+        .ok_or(PngError::ChunkNotFound)?;
+    let source = png.remove_chunk(&source_type)?;
+
+    let message = match &source_type[..] {
+        "tEXt" | "zTXt" => TextChunk::try_from(&source)?.text,
+        _ => String::from_utf8(source.data().to_vec())?,
+    };
+    let converted = match &c.new_type.to_string()[..] {
+        "tEXt" | "zTXt" => {
+            let keyword = c.keyword.as_deref().ok_or("Converting to a text chunk needs --keyword")?;
+            let text = TextChunk::new(keyword, &message)?;
+            if c.new_type.to_string() == "zTXt" {
+                text.to_compressed_chunk()?
+            } else {
+                text.to_chunk()?
             }
+        }
+        _ => Chunk::new(c.new_type, message.into_bytes()),
+    };
+    png.insert_chunk(index, converted)?;
+    write_png(destination, &png, c.backup, c.force)?;
+
+    Ok(())
+}
+
+// SHA-256 of the file as hex
+pub fn hash(h: Hash, out: &mut impl Write) -> Result<()> {
+    let img = read_input(&h.file_path)?;
+    let digest = if h.critical_only {
+        let png = check_trailing(parse_png(&img, h.ignore_crc)?, h.strict)?;
+        let mut hasher = Sha256::new();
+        // the signature, already checked by the parse above
+        hasher.update(&img[..8]);
+        for chunk in png.chunks().iter().filter(|chunk| chunk.chunk_type().is_critical()) {
+            hasher.update(chunk.as_bytes());
+        }
+        hasher.finalize()
+    } else {
+        Sha256::digest(img.as_slice())
+    };
+    writeln!(out, "{}", render::render(&digest, render::Format::Hex))?;
+
+    Ok(())
+}
+
+// restore the backup written by --backup over FILE_PATH
+pub fn undo(u: Undo) -> Result<()> {
+    check_writable(&u.file_path)?;
+    let backup = backup_path(&u.file_path);
+    if !backup.exists() {
+        return Err(format!("No backup found at {}", backup.display()).into());
+    }
+
+    fs::copy(&backup, &u.file_path)?;
+    if u.discard {
+        fs::remove_file(&backup)?;
+    }
+    println!("Restored {} from {}", u.file_path.display(), backup.display());
+
+    Ok(())
+}
+
+// ancillary, private and safe to copy so it never gets in the way of the image
+const TAG_CHUNK_TYPE: &str = "pmTg";
+
+fn tag_chunk() -> Result<Chunk> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let tag = format!("{} {} encoded at {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), timestamp);
+    Ok(Chunk::new(ChunkType::from_str(TAG_CHUNK_TYPE)?, tag.into_bytes()))
+}
+
+// with 'ignore_crc' chunks with a bad CRC are kept and reported on stderr
+fn parse_png(bytes: &[u8], ignore_crc: bool) -> Result<Png> {
+    parse_png_with(
+        bytes,
+        PngParseOptions {
+            verify_crc: !ignore_crc,
+            ..PngParseOptions::default()
         },
-        Err(e) => eprintln!("Error: {}", e),
+    )
+}
+
+fn parse_png_with(bytes: &[u8], options: PngParseOptions) -> Result<Png> {
+    let (png, warnings) = Png::try_from_opts(bytes, options)?;
+    for warning in warnings {
+        warn!("{}", warning);
+    }
+    Ok(png)
+}
+
+// bytes after IEND are reported, with 'strict' they are an error
+fn check_trailing(png: Png, strict: bool) -> Result<Png> {
+    let trailing = png.trailing_data().len();
+    if trailing == 0 {
+        Ok(png)
+    } else if strict {
+        Err(format!("Found {} bytes of trailing data after IEND", trailing).into())
+    } else {
+        warn!("Ignoring {} bytes of trailing data after IEND", trailing);
+        Ok(png)
+    }
+}
+
+// bytes of an input PNG, memory-mapped rather than copied to the heap when built
+// with the 'mmap' feature
+enum Input {
+    Bytes(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl Input {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Input::Bytes(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            Input::Mapped(map) => map,
+        }
+    }
+}
+
+impl Deref for Input {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+// read a PNG from disk, from stdin for '-', or over http(s) when built with the 'network' feature
+fn read_input(path: &Path) -> Result<Input> {
+    if is_stdio(path) {
+        let mut bytes = Vec::new();
+        io::stdin().lock().read_to_end(&mut bytes)?;
+        Ok(Input::Bytes(bytes))
+    } else if is_url(path) {
+        Ok(Input::Bytes(fetch(path.to_str().unwrap_or_default())?))
+    } else {
+        read_file(path)
+    }
+}
+
+#[cfg(feature = "mmap")]
+fn read_file(path: &Path) -> Result<Input> {
+    let file = fs::File::open(path).map_err(|err| read_error(path, err))?;
+    // safety: the map is only read, a file truncated by another process while mapped is not guarded against
+    match unsafe { memmap2::Mmap::map(&file) } {
+        Ok(map) => Ok(Input::Mapped(map)),
+        Err(err) => {
+            info!("Could not map {}, reading it instead: {}", path.display(), err);
+            Ok(Input::Bytes(fs::read(path).map_err(|err| read_error(path, err))?))
+        }
+    }
+}
+
+#[cfg(not(feature = "mmap"))]
+fn read_file(path: &Path) -> Result<Input> {
+    Ok(Input::Bytes(fs::read(path).map_err(|err| read_error(path, err))?))
+}
+
+// names the file like Png::open does, keeping the io::ErrorKind so a missing file still exits with 3
+fn read_error(path: &Path, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("Could not read {}: {}", path.display(), err))
+}
+
+// like Png::open, but '-' reads the PNG from stdin and URLs are fetched
+fn open_png(path: &Path) -> Result<Png> {
+    Png::try_from(read_input(path)?.as_slice())
+}
+
+// '-' stands for stdin when reading and stdout when writing
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+#[cfg(feature = "network")]
+fn fetch(url: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    ureq::get(url).call()?.into_reader().read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(not(feature = "network"))]
+fn fetch(_url: &str) -> Result<Vec<u8>> {
+    Err("Reading from a URL requires the 'network' feature".into())
+}
+
+// commands that modify a file write it back to where it was read from
+fn check_writable(path: &Path) -> Result<()> {
+    if is_url(path) {
+        Err("Cannot write back to a URL".into())
+    } else {
+        Ok(())
+    }
+}
+
+// run 'f' on every .png file under 'dir' using up to 'threads' threads, printing how many
+// succeeded in each directory. Failures are reported in file order once a directory is done
+fn for_each_png(
+    dir: &Path,
+    verb: &str,
+    threads: usize,
+    progress: &Progress,
+    f: impl Fn(&Path) -> Result<()> + Sync,
+) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()).into());
+    }
+
+    let dirs = png_files(dir)?;
+    let total: usize = dirs.iter().map(|(_, files)| files.len()).sum();
+    let done = AtomicUsize::new(0);
+    let mut failed = 0;
+    for (dir, files) in dirs {
+        let mut succeeded = 0;
+        // errors are boxed without Send, so they cross back from the threads as strings
+        let results = run_parallel(&files, threads, |file| {
+            let result = f(file).map_err(|err| err.to_string());
+            let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+            progress.report(format_args!("{}/{} file(s) {}", done, total, verb));
+            result
+        });
+        for (file, result) in files.iter().zip(results) {
+            match result {
+                Ok(()) => succeeded += 1,
+                Err(err) => {
+                    warn!("{}: {}", file.display(), err);
+                    failed += 1;
+                }
+            }
+        }
+        eprintln!("{}: {} of {} file(s) {}", dir.display(), succeeded, files.len(), verb);
+    }
+
+    if failed > 0 {
+        Err(format!("{} file(s) failed", failed).into())
+    } else {
+        Ok(())
     }
+}
+
+// 'f' applied to every item in order, split into contiguous runs across 'threads' scoped threads
+fn run_parallel<T: Sync, R: Send>(items: &[T], threads: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    if threads <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let per_thread = items.len().div_ceil(threads);
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(per_thread)
+            .map(|part| scope.spawn(move || part.iter().map(f).collect::<Vec<R>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("batch thread panicked"))
+            .collect()
+    })
+}
+
+// files a previous batch run finished, one path per line. A missing manifest means nothing was done yet
+fn read_manifest(manifest: &Path) -> Result<HashSet<PathBuf>> {
+    match fs::read_to_string(manifest) {
+        Ok(contents) => Ok(contents.lines().map(PathBuf::from).collect()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(err) => Err(err.into()),
+    }
+}
 
+// appended as soon as 'file' is written so an interrupted run keeps its progress
+fn record_in_manifest(manifest: &Path, file: &Path) -> Result<()> {
+    let mut manifest = fs::OpenOptions::new().create(true).append(true).open(manifest)?;
+    writeln!(manifest, "{}", file.display())?;
     Ok(())
-}
\ No newline at end of file
+}
+
+// .png files under 'root' grouped by directory, other files are skipped
+fn png_files(root: &Path) -> Result<Vec<(PathBuf, Vec<PathBuf>)>> {
+    let mut visited = HashSet::new();
+    let mut pending = vec![root.to_path_buf()];
+    let mut found = Vec::new();
+
+    while let Some(dir) = pending.pop() {
+        // a symlink loop leads back to a directory whose canonical path was already visited
+        if !visited.insert(fs::canonicalize(&dir)?) {
+            continue;
+        }
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")) {
+                files.push(path);
+            }
+        }
+        if !files.is_empty() {
+            files.sort();
+            found.push((dir, files));
+        }
+    }
+
+    found.sort();
+    Ok(found)
+}
+
+fn find_encoding(name: &str) -> Result<&'static Encoding> {
+    Encoding::for_label(name.as_bytes()).ok_or_else(|| format!("Unknown encoding {}", name).into())
+}
+
+fn encode_text(text: &str, encoding: &'static Encoding) -> Vec<u8> {
+    // encoding_rs only decodes UTF-16, encoding to it gives UTF-8 as the web platform requires
+    if encoding == UTF_16LE {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    } else if encoding == UTF_16BE {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    } else {
+        encoding.encode(text).0.into_owned()
+    }
+}
+
+// message files written on Windows end lines with CRLF
+fn normalize_newlines(message: &[u8]) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(message.len());
+    for (i, &byte) in message.iter().enumerate() {
+        if !(byte == b'\r' && message.get(i + 1) == Some(&b'\n')) {
+            normalized.push(byte);
+        }
+    }
+    normalized
+}
+
+// bytes other than printable ASCII, newlines and tabs become '?', multi-byte characters
+// become one '?' per byte
+fn force_ascii(message: &[u8]) -> Vec<u8> {
+    message
+        .iter()
+        .map(|&byte| match byte {
+            b'\n' | b'\t' | b' '..=b'~' => byte,
+            _ => b'?',
+        })
+        .collect()
+}
+
+// where encode and remove write their result, overwriting the input has to be asked for
+fn destination<'a>(file_path: &'a Path, output: &'a Option<PathBuf>, in_place: bool) -> Result<&'a Path> {
+    match output {
+        Some(output) => Ok(output),
+        None if in_place => Ok(file_path),
+        None => Err(format!(
+            "Refusing to overwrite {}, pass --in-place to modify it or --output to write elsewhere",
+            file_path.display()
+        )
+        .into()),
+    }
+}
+
+// write 'png' to 'path', or stdout for '-', first copying any existing file to '<path>.bak' if asked to
+fn write_png(path: &Path, png: &Png, backup: bool, force: bool) -> Result<()> {
+    if is_stdio(path) {
+        png.write_to(&mut io::stdout().lock())?;
+        return Ok(());
+    }
+    if backup && path.exists() {
+        let backup = backup_path(path);
+        if backup.exists() && !force {
+            return Err(format!("Backup {} already exists, pass --force to overwrite it", backup.display()).into());
+        }
+        fs::copy(path, &backup)?;
+    }
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    png.write_to(&mut file)?;
+    file.flush()?;
+    Ok(())
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+// with 'ignore_case' the type of the first chunk that matches 'chunk_type' ignoring case,
+// so 'rust' finds a ruSt chunk. The case of each letter is a property bit, so this is opt-in
+fn resolve_chunk_type(png: &Png, chunk_type: &ChunkType, ignore_case: bool) -> String {
+    let wanted = chunk_type.to_string();
+    if !ignore_case {
+        return wanted;
+    }
+    png.chunks()
+        .iter()
+        .map(|chunk| chunk.chunk_type().to_string())
+        .find(|found| found.eq_ignore_ascii_case(&wanted))
+        .unwrap_or(wanted)
+}
+
+fn read_ihdr(png: &Png) -> Result<Ihdr> {
+    Ihdr::try_from(png.chunk_by_type("IHDR").ok_or("Could not find IHDR chunk")?)
+}
+
+// public types are reserved for the PNG spec and registered extensions, a message stored
+// as one may be misread by other tools. tEXt, zTXt and iTXt are the ones encode --text and --itext write
+fn public_type_warning(chunk_type: &ChunkType) -> Option<String> {
+    let name = chunk_type.to_string();
+    if !chunk_type.is_public() || ["tEXt", "zTXt", "iTXt"].contains(&name.as_str()) {
+        return None;
+    }
+    let mut private = *chunk_type;
+    private.set_public(false);
+    Some(format!(
+        "{} is a public chunk type and may collide with a registered chunk, consider the private {} instead",
+        chunk_type, private
+    ))
+}
+
+// decoders treat critical chunks as part of the image, so hiding a message in one breaks the picture
+fn check_chunk_type(chunk_type: &ChunkType, force: bool) -> Result<()> {
+    if !chunk_type.is_critical() {
+        return Ok(());
+    }
+
+    let warning = format!(
+        "{} is a critical chunk type and will corrupt the image, use an ancillary private type like ruSt instead",
+        chunk_type
+    );
+    if force {
+        warn!("{}", warning);
+        Ok(())
+    } else {
+        Err(format!("{} (or pass --force)", warning).into())
+    }
+}
+
+// the messages stored under 'chunk_type' in file order, a split message counts as one
+fn hidden_messages(png: &Png, chunk_type: &str) -> Result<Vec<Vec<u8>>> {
+    let chunks = png.chunks_by_type(chunk_type);
+    if chunks.is_empty() {
+        return Err(PngError::ChunkNotFound.into());
+    }
+
+    split::join_messages(&chunks)
+}
+
+// a few bytes of context on each side of a match
+fn snippet(data: &[u8], position: usize, len: usize) -> String {
+    const CONTEXT: usize = 16;
+    let start = position.saturating_sub(CONTEXT);
+    let end = (position + len + CONTEXT).min(data.len());
+    String::from_utf8_lossy(&data[start..end]).into_owned()
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            if pair.len() != 2 {
+                return Err("Hex pattern must have an even number of digits".into());
+            }
+            Ok(u8::from_str_radix(std::str::from_utf8(pair)?, 16)?)
+        })
+        .collect()
+}
+
+// progress lines like "3/10 file(s) encoded", only written with --verbose. Worker threads
+// share it, so the writer sits behind a lock
+pub struct Progress<'a> {
+    verbose: bool,
+    writer: Mutex<Box<dyn Write + Send + 'a>>,
+}
+
+impl<'a> Progress<'a> {
+    pub fn new(verbose: bool, writer: impl Write + Send + 'a) -> Self {
+        Progress {
+            verbose,
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+
+    // never writes anything, for tests that don't look at progress
+    #[cfg(test)]
+    pub fn quiet() -> Progress<'static> {
+        Progress::new(false, io::sink())
+    }
+
+    fn report(&self, line: fmt::Arguments) {
+        if !self.verbose {
+            return;
+        }
+        if let Ok(mut writer) = self.writer.lock() {
+            // progress is best effort, a closed stderr shouldn't stop the work
+            let _ = writeln!(writer, "{}", line);
+        }
+    }
+}
+
+// diagnostics go through the log, --verbose shows informational messages as well
+pub fn log_level(verbose: bool) -> LevelFilter {
+    if verbose {
+        LevelFilter::Info
+    } else {
+        LevelFilter::Warn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+    use std::process;
+
+    use clap::Clap;
+
+    use crate::commands::{CmdOptions, Commands};
+
+    // write a minimal PNG holding 'chunks' between IHDR and IEND to a temporary file
+    fn testing_file(name: &str, chunks: &[(&str, &[u8])]) -> PathBuf {
+        let ihdr = [0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0];
+        let bytes: Vec<u8> = [137, 80, 78, 71, 13, 10, 26, 10]
+            .iter()
+            .copied()
+            .chain(testing_chunk("IHDR", &ihdr).as_bytes())
+            .chain(chunks.iter().flat_map(|(chunk_type, data)| testing_chunk(chunk_type, data).as_bytes()))
+            .chain(testing_chunk("IEND", &[]).as_bytes())
+            .collect();
+
+        let path = env::temp_dir().join(format!("png_message_{}_{}.png", process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn testing_chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_decode_raw_writes_exact_bytes() {
+        let payload: &[u8] = &[0, 159, 146, 150, 10, 255];
+        let path = testing_file("decode_raw", &[("ruSt", payload)]);
+        let mut out = Vec::new();
+
+        decode(Decode::parse_from(["decode", path.to_str().unwrap(), "ruSt", "--raw"]), &mut out).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(out, payload);
+    }
+
+    #[test]
+    fn test_decode_labels_message() {
+        let path = testing_file("decode_label", &[("ruSt", b"hello")]);
+        let mut out = Vec::new();
+
+        decode(Decode::parse_from(["decode", path.to_str().unwrap(), "ruSt"]), &mut out).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(out, b"Hidden message: hello\n");
+    }
+
+    #[test]
+    fn test_hash_critical_only() {
+        let path = testing_file("hash", &[("IDAT", b"pixels")]);
+        let path_str = path.to_str().unwrap();
+        let (mut whole_before, mut critical_before) = (Vec::new(), Vec::new());
+        let (mut whole_after, mut critical_after) = (Vec::new(), Vec::new());
+
+        hash(Hash::parse_from(["hash", path_str]), &mut whole_before).unwrap();
+        hash(Hash::parse_from(["hash", path_str, "--critical-only"]), &mut critical_before).unwrap();
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "message"]), &Progress::quiet()).unwrap();
+        hash(Hash::parse_from(["hash", path_str]), &mut whole_after).unwrap();
+        hash(Hash::parse_from(["hash", path_str, "--critical-only"]), &mut critical_after).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(critical_before.len(), 65);
+        assert_eq!(critical_before, critical_after);
+        assert_ne!(whole_before, whole_after);
+    }
+
+    #[test]
+    fn test_convert_raw_to_text() {
+        let path = testing_file("convert", &[("ruSt", b"hello")]);
+        let path_str = path.to_str().unwrap();
+
+        convert(Convert::parse_from(["convert", "--in-place", path_str, "ruSt", "tEXt", "--keyword", "Comment"])).unwrap();
+        let png = Png::open(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(png.chunk_by_type("ruSt").is_none());
+        // exiftool and friends read tEXt as keyword, NUL, Latin-1 text
+        let text = png.chunk_by_type("tEXt").unwrap();
+        assert_eq!(text.data(), b"Comment\0hello");
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "tEXt");
+    }
+
+    #[test]
+    fn test_convert_text_to_raw() {
+        let path = testing_file("convert_back", &[("tEXt", b"Comment\0hello")]);
+        let path_str = path.to_str().unwrap();
+
+        assert!(convert(Convert::parse_from(["convert", "--in-place", path_str, "tEXt", "zTXt"])).is_err());
+        convert(Convert::parse_from(["convert", "--in-place", path_str, "tEXt", "ruSt"])).unwrap();
+        let png = Png::open(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"hello");
+    }
+
+    #[test]
+    fn test_encode_and_decode_itext() {
+        let path = testing_file("itext", &[]);
+        let path_str = path.to_str().unwrap();
+        let mut out = Vec::new();
+
+        encode(Encode::parse_from([
+            "encode", "--in-place", path_str, "iTXt", "\u{3053}\u{3093}\u{306b}\u{3061}\u{306f}", "--itext", "Comment", "--language", "ja",
+        ]), &Progress::quiet())
+        .unwrap();
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "iTXt", "hello", "--itext", "Comment", "--ztext"]), &Progress::quiet()).unwrap();
+        decode(Decode::parse_from(["decode", path_str, "iTXt", "--itext", "--all"]), &mut out).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Comment [ja] (): \u{3053}\u{3093}\u{306b}\u{3061}\u{306f}\nComment [] (): hello\n"
+        );
+    }
+
+    #[test]
+    fn test_decode_tee() {
+        let payload: &[u8] = b"hello \xff\x00";
+        let path = testing_file("decode_tee", &[("ruSt", payload)]);
+        let tee = env::temp_dir().join(format!("png_message_{}_decode_tee.bin", process::id()));
+        let mut out = Vec::new();
+
+        decode(Decode::parse_from(["decode", path.to_str().unwrap(), "ruSt", "--tee", tee.to_str().unwrap()]), &mut out).unwrap();
+        let written = fs::read(&tee).unwrap();
+        fs::remove_file(path).unwrap();
+        fs::remove_file(tee).unwrap();
+
+        assert_eq!(out, b"Hidden message: hello \\xff\\x00\n");
+        assert_eq!(written, payload);
+    }
+
+    #[test]
+    fn test_decode_no_newline() {
+        let path = testing_file("decode_no_newline", &[("ruSt", b"hello")]);
+        let path_str = path.to_str().unwrap();
+        let mut with = Vec::new();
+        let mut without = Vec::new();
+
+        decode(Decode::parse_from(["decode", path_str, "ruSt"]), &mut with).unwrap();
+        decode(Decode::parse_from(["decode", path_str, "ruSt", "--no-newline"]), &mut without).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(without, b"Hidden message: hello");
+        assert_eq!(with.len() - without.len(), 1);
+    }
+
+    #[test]
+    fn test_progress_only_when_verbose() {
+        let path = testing_file("progress", &[("ruSt", b"message")]);
+        let path_str = path.to_str().unwrap();
+        let run = |args: &[&str]| {
+            let options = CmdOptions::parse_from(args);
+            let mut lines = Vec::new();
+            let progress = Progress::new(options.verbose, &mut lines);
+            match options.sub_command {
+                Commands::Print(p) => print(p, &mut Vec::new(), &progress).unwrap(),
+                _ => unreachable!(),
+            }
+            drop(progress);
+            String::from_utf8(lines).unwrap()
+        };
+
+        let quiet = run(&["png_message", "print", path_str]);
+        let verbose = run(&["png_message", "-v", "print", path_str]);
+        let lenient = run(&["png_message", "-v", "print", path_str, "--ignore-crc"]);
+        fs::remove_file(path).unwrap();
+
+        assert!(quiet.is_empty());
+        // a line after each of IHDR, ruSt and IEND
+        let expected = "Parsed 1 chunk(s), 33/64 bytes\nParsed 2 chunk(s), 52/64 bytes\nParsed 3 chunk(s), 64/64 bytes\n";
+        assert_eq!(verbose, expected);
+        assert_eq!(lenient, expected);
+    }
+
+    #[test]
+    fn test_batch_progress_only_when_verbose() {
+        let root = env::temp_dir().join(format!("png_message_{}_batch_progress", process::id()));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        let source = testing_file("batch_progress_source", &[]);
+        for png in [root.join("a.png"), root.join("sub").join("b.png"), root.join("sub").join("c.png")] {
+            fs::copy(&source, png).unwrap();
+        }
+        fs::remove_file(source).unwrap();
+        let root_str = root.to_str().unwrap();
+        let run = |args: &[&str]| {
+            let options = CmdOptions::parse_from(args);
+            let mut lines = Vec::new();
+            let progress = Progress::new(options.verbose, &mut lines);
+            match options.sub_command {
+                Commands::Encode(e) => encode(e, &progress).unwrap(),
+                _ => unreachable!(),
+            }
+            drop(progress);
+            String::from_utf8(lines).unwrap()
+        };
+
+        let quiet = run(&["png_message", "encode", "--in-place", "--recursive", root_str, "ruSt", "one"]);
+        let verbose = run(&[
+            "png_message",
+            "-v",
+            "encode",
+            "--in-place",
+            "--recursive",
+            "--threads",
+            "2",
+            root_str,
+            "ruSt",
+            "two",
+        ]);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(quiet.is_empty());
+        assert_eq!(verbose, "1/3 file(s) encoded\n2/3 file(s) encoded\n3/3 file(s) encoded\n");
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("deadBEEF").unwrap(), vec![0xde, 0xad, 0xbe, 0xef]);
+        assert!(parse_hex("abc").is_err());
+        assert!(parse_hex("zz").is_err());
+        assert!(parse_hex("é0").is_err());
+    }
+
+    #[test]
+    fn test_repl_scripted_session() {
+        let path = testing_file("repl", &[("ruSt", b"hello"), ("teSt", b"bye")]);
+        let input = "list\ndecode ruSt\nremove ruSt\ndecode ruSt\nsave\nquit\nlist\n";
+        let mut out = Vec::new();
+
+        repl(Repl::parse_from(["repl", "--in-place", path.to_str().unwrap()]), input.as_bytes(), &mut out).unwrap();
+        let saved = Png::open(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("IHDR\nruSt\nteSt\nIEND\n"));
+        assert!(out.contains("Hidden message: hello\n"));
+        assert!(out.contains("Removed ruSt\n"));
+        assert!(out.contains("Error: Could not find chunk\n"));
+        assert_eq!(out.matches("IHDR").count(), 1);
+        assert!(saved.chunk_by_type("ruSt").is_none());
+        assert!(saved.chunk_by_type("teSt").is_some());
+    }
+
+    #[test]
+    fn test_repl_save_requires_output_or_in_place() {
+        let path = testing_file("repl_save", &[("ruSt", b"hello")]);
+        let path_str = path.to_str().unwrap();
+        let original = fs::read(&path).unwrap();
+        let input = "remove ruSt\nsave\n";
+        let mut refused = Vec::new();
+        let mut backed_up = Vec::new();
+
+        repl(Repl::parse_from(["repl", path_str]), input.as_bytes(), &mut refused).unwrap();
+        let unchanged = fs::read(&path).unwrap();
+        repl(Repl::parse_from(["repl", "--in-place", "--backup", path_str]), input.as_bytes(), &mut backed_up).unwrap();
+        let saved = Png::open(&path).unwrap();
+        let backup = fs::read(backup_path(&path)).unwrap();
+        fs::remove_file(backup_path(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(String::from_utf8(refused).unwrap().contains("Error: Refusing to overwrite"));
+        assert_eq!(unchanged, original);
+        assert!(String::from_utf8(backed_up).unwrap().contains("Saved "));
+        assert!(saved.chunk_by_type("ruSt").is_none());
+        assert_eq!(backup, original);
+    }
+
+    #[test]
+    fn test_copy_chunk() {
+        let source = testing_file("copy_source", &[("ruSt", b"copied message")]);
+        let destination = testing_file("copy_destination", &[]);
+        let mut out = Vec::new();
+
+        copy_chunk(CopyChunk::parse_from([
+            "copy-chunk",
+            "--in-place",
+            source.to_str().unwrap(),
+            destination.to_str().unwrap(),
+            "ruSt",
+        ]))
+        .unwrap();
+        decode(Decode::parse_from(["decode", destination.to_str().unwrap(), "ruSt"]), &mut out).unwrap();
+        let missing = copy_chunk(CopyChunk::parse_from([
+            "copy-chunk",
+            "--in-place",
+            source.to_str().unwrap(),
+            destination.to_str().unwrap(),
+            "teSt",
+        ]));
+        fs::remove_file(source).unwrap();
+        fs::remove_file(destination).unwrap();
+
+        assert_eq!(out, b"Hidden message: copied message\n");
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn test_encode_critical_type_requires_force() {
+        let path = testing_file("encode_critical", &[]);
+        let path_str = path.to_str().unwrap();
+
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "ancillary"]), &Progress::quiet()).unwrap();
+        let refused = encode(Encode::parse_from(["encode", "--in-place", path_str, "IDAT", "critical"]), &Progress::quiet());
+        let unchanged = Png::open(&path).unwrap();
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "IDAT", "critical", "--force"]), &Progress::quiet()).unwrap();
+        let forced = Png::open(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(refused.is_err());
+        assert!(unchanged.chunk_by_type("IDAT").is_none());
+        assert!(forced.chunk_by_type("IDAT").is_some());
+    }
+
+    #[test]
+    fn test_decode_two_split_messages() {
+        let path = testing_file("decode_two_split", &[]);
+        let path_str = path.to_str().unwrap();
+        for message in ["the first split message", "and the second one"] {
+            encode(Encode::parse_from(["encode", path_str, "ruSt", message, "--split", "5", "--in-place"]), &Progress::quiet()).unwrap();
+        }
+        let mut out = Vec::new();
+
+        decode(Decode::parse_from(["decode", path_str, "ruSt", "--all"]), &mut out).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            out,
+            b"Hidden message: the first split message\nHidden message: and the second one\n"
+        );
+    }
+
+    #[test]
+    fn test_decode_count() {
+        let chunks: Vec<(&str, &[u8])> = vec![
+            ("ruSt", b"one"),
+            ("ruSt", b"two"),
+            ("ruSt", b"three"),
+            ("ruSt", b"four"),
+            ("ruSt", b"five"),
+        ];
+        let path = testing_file("decode_count", &chunks);
+        let path_str = path.to_str().unwrap();
+        let mut first = Vec::new();
+        let mut two = Vec::new();
+        let mut all = Vec::new();
+
+        decode(Decode::parse_from(["decode", path_str, "ruSt"]), &mut first).unwrap();
+        decode(Decode::parse_from(["decode", path_str, "ruSt", "--count", "2"]), &mut two).unwrap();
+        decode(Decode::parse_from(["decode", path_str, "ruSt", "--all"]), &mut all).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(first, b"Hidden message: one\n");
+        assert_eq!(two, b"Hidden message: one\nHidden message: two\n");
+        assert_eq!(String::from_utf8(all).unwrap().lines().count(), 5);
+    }
+
+    #[test]
+    fn test_print_chars() {
+        let path = testing_file("print_chars", &[("ruSt", "héllo ✓".as_bytes()), ("teSt", &[0xff])]);
+        let mut out = Vec::new();
+
+        print(
+            Print::parse_from(["print", path.to_str().unwrap(), "--chars", "--color", "never"]),
+            &mut out,
+            &Progress::quiet(),
+        )
+        .unwrap();
+        fs::remove_file(path).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Data bytes: 10\nCRC: "));
+        assert!(out.contains("Characters: 7\n"));
+        // teSt isn't UTF-8, IHDR's bytes and the empty IEND happen to be
+        assert_eq!(out.matches("Characters: ").count(), 3);
+    }
+
+    #[test]
+    fn test_print_preview() {
+        let path = testing_file("print_preview", &[("ruSt", b"hidden message"), ("teSt", b"hi")]);
+        let mut out = Vec::new();
+
+        print(
+            Print::parse_from(["print", path.to_str().unwrap(), "--preview", "6", "--color", "never"]),
+            &mut out,
+            &Progress::quiet(),
+        )
+        .unwrap();
+        fs::remove_file(path).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let previews: Vec<&str> = out.lines().filter(|line| line.starts_with("Preview: ")).collect();
+        // IHDR and IEND included, IEND has no data
+        assert_eq!(previews.len(), 4);
+        assert_eq!(previews[1], "Preview: hidden");
+        assert_eq!(previews[2], "Preview: hi");
+        assert_eq!(previews[3], "Preview: ");
+    }
+
+    #[test]
+    fn test_type_regex() {
+        let chunks: Vec<(&str, &[u8])> = vec![("IDAT", b"pixels"), ("ruSt", b"hidden"), ("tEXt", b"Comment\0hi")];
+        let path = testing_file("type_regex", &chunks);
+        let path_str = path.to_str().unwrap();
+        let mut listed = Vec::new();
+        let mut decoded = Vec::new();
+
+        list(List::parse_from(["list", path_str, "--type-regex", "^[a-z]"]), &mut listed).unwrap();
+        decode(Decode::parse_from(["decode", path_str, "--type-regex", "^r"]), &mut decoded).unwrap();
+        let invalid = Decode::try_parse_from(["decode", path_str, "--type-regex", "[a-z"]);
+        fs::remove_file(path).unwrap();
+
+        let listed = String::from_utf8(listed).unwrap();
+        let types: Vec<&str> = listed.lines().map(|line| line.split_whitespace().nth(1).unwrap()).collect();
+        assert_eq!(types, ["ruSt", "tEXt"]);
+        assert_eq!(decoded, b"Hidden message: hidden\n");
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_length_filters() {
+        let chunks: Vec<(&str, &[u8])> = vec![("ruSt", b"a"), ("ruSt", b"abcde"), ("ruSt", b"abcdefghij")];
+        let path = testing_file("length_filters", &chunks);
+        let path_str = path.to_str().unwrap();
+        let mut listed = Vec::new();
+        let mut found = Vec::new();
+
+        list(List::parse_from(["list", path_str, "--min-length", "2", "--max-length", "13"]), &mut listed).unwrap();
+        search(Search::parse_from(["search", path_str, "a", "--min-length", "5"]), &mut found).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let listed = String::from_utf8(listed).unwrap();
+        let lengths: Vec<&str> = listed.lines().map(|line| line.split_whitespace().nth(2).unwrap()).collect();
+        // IHDR is 13 bytes, IEND is empty
+        assert_eq!(lengths, ["13", "5", "10"]);
+        assert_eq!(String::from_utf8(found).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn test_print_exclude() {
+        let path = testing_file("print_exclude", &[("IDAT", b"pixels"), ("ruSt", b"message")]);
+        let path_str = path.to_str().unwrap();
+        let mut out = Vec::new();
+        let mut listed = Vec::new();
+
+        print(
+            Print::parse_from(["print", path_str, "--exclude", "IDAT", "--color", "never"]),
+            &mut out,
+            &Progress::quiet(),
+        )
+        .unwrap();
+        list(List::parse_from(["list", path_str, "--exclude", "IDAT", "--exclude", "IEND"]), &mut listed).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains("IDAT"));
+        assert!(out.contains("ruSt"));
+        let listed = String::from_utf8(listed).unwrap();
+        assert_eq!(listed.lines().count(), 2);
+        assert!(listed.lines().nth(1).unwrap().starts_with("   2 ruSt"));
+    }
+
+    #[test]
+    fn test_strip_exclude_overrides_keep() {
+        let path = testing_file("strip_exclude", &[("tEXt", b"Comment\0hi"), ("ruSt", b"message")]);
+        let path_str = path.to_str().unwrap();
+
+        let critical = strip(Strip::parse_from(["strip", "--in-place", path_str, "--exclude", "IDAT"]), &mut Vec::new(), &Progress::quiet());
+        strip(Strip::parse_from(["strip", "--in-place", path_str, "--keep", "tEXt", "--keep", "ruSt", "--exclude", "tEXt"]), &mut Vec::new(), &Progress::quiet()).unwrap();
+        let png = Png::open(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(critical.is_err());
+        assert!(png.chunk_by_type("tEXt").is_none());
+        assert!(png.chunk_by_type("ruSt").is_some());
+    }
+
+    #[test]
+    fn test_encode_if_absent() {
+        let path = testing_file("encode_if_absent", &[]);
+        let path_str = path.to_str().unwrap();
+
+        for _ in 0..2 {
+            encode(Encode::parse_from(["encode", "--in-place", "--if-absent", path_str, "ruSt", "message"]), &Progress::quiet()).unwrap();
+        }
+        let png = Png::open(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(png.chunks_by_type("ruSt").len(), 1);
+    }
+
+    #[test]
+    fn test_search_count_only() {
+        let path = testing_file("search_count", &[("ruSt", b"needle one"), ("teSt", b"needle two")]);
+        let path_str = path.to_str().unwrap();
+        let mut found = Vec::new();
+        let mut absent = Vec::new();
+
+        let found_count = search(Search::parse_from(["search", path_str, "needle", "--count-only"]), &mut found).unwrap();
+        let absent_count = search(Search::parse_from(["search", path_str, "haystack", "--count-only"]), &mut absent).unwrap();
+        fs::remove_file(path).unwrap();
+
+        // main exits with 1 when --count-only finds nothing
+        assert_eq!(found_count, 2);
+        assert_eq!(found, b"2\n");
+        assert_eq!(absent_count, 0);
+        assert_eq!(absent, b"0\n");
+    }
+
+    #[test]
+    fn test_search_ignore_case() {
+        let path = testing_file("search_ignore_case", &[("ruSt", b"a Secret message"), ("teSt", b"no match")]);
+        let path_str = path.to_str().unwrap();
+        let mut exact = Vec::new();
+        let mut ignoring = Vec::new();
+        let mut by_type = Vec::new();
+
+        search(Search::parse_from(["search", path_str, "SECRET"]), &mut exact).unwrap();
+        search(Search::parse_from(["search", path_str, "SECRET", "-i"]), &mut ignoring).unwrap();
+        let type_count = search(
+            Search::parse_from(["search", path_str, "e", "--type-regex", "^RUST$", "--ignore-case"]),
+            &mut by_type,
+        )
+        .unwrap();
+        let exact_type_count =
+            search(Search::parse_from(["search", path_str, "e", "--type-regex", "^RUST$"]), &mut Vec::new()).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(exact.is_empty());
+        assert_eq!(ignoring, b"ruSt: a Secret message\n");
+        assert_eq!(type_count, 1);
+        assert!(String::from_utf8(by_type).unwrap().starts_with("ruSt: "));
+        assert_eq!(exact_type_count, 0);
+    }
+
+    #[test]
+    fn test_diff_encoded_copy() {
+        let path = testing_file("diff_original", &[("IDAT", b"pixels")]);
+        let path_str = path.to_str().unwrap();
+        let encoded = path.with_file_name(format!("png_message_{}_diff_encoded.png", process::id()));
+        let encoded_str = encoded.to_str().unwrap();
+        encode(Encode::parse_from(["encode", path_str, "ruSt", "hidden", "-o", encoded_str]), &Progress::quiet()).unwrap();
+        let mut out = Vec::new();
+
+        diff(Diff::parse_from(["diff", path_str, encoded_str]), &mut out).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&encoded).unwrap();
+
+        let lines: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.split(" (crc").next().unwrap().to_string())
+            .collect();
+        assert_eq!(lines, ["   0 same     IHDR", "   1 same     IDAT", "   2 added    ruSt", "   3 same     IEND"]);
+    }
+
+    #[test]
+    fn test_missing_file_error_names_the_file() {
+        let path = env::temp_dir().join(format!("png_message_{}_missing_named.png", process::id()));
+        let path_str = path.to_str().unwrap();
+
+        let decoded = decode(Decode::parse_from(["decode", path_str, "ruSt"]), &mut Vec::new()).unwrap_err();
+        let deduped = dedupe(Dedupe::parse_from(["dedupe", "--in-place", path_str])).unwrap_err();
+
+        for err in [decoded, deduped] {
+            assert!(err.to_string().starts_with(&format!("Could not read {}", path.display())));
+            assert_eq!(err.downcast_ref::<io::Error>().unwrap().kind(), io::ErrorKind::NotFound);
+        }
+    }
+
+    #[test]
+    fn test_missing_file_is_an_error() {
+        let path = env::temp_dir().join(format!("png_message_{}_missing.png", process::id()));
+        let path_str = path.to_str().unwrap();
+
+        assert!(encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "message"]), &Progress::quiet()).is_err());
+        assert!(decode(Decode::parse_from(["decode", path_str, "ruSt"]), &mut Vec::new()).is_err());
+        assert!(remove(Remove::parse_from(["remove", "--in-place", path_str, "ruSt"]), &mut Vec::new()).is_err());
+        assert!(print(Print::parse_from(["print", path_str]), &mut Vec::new(), &Progress::quiet()).is_err());
+        assert!(search(Search::parse_from(["search", path_str, "message"]), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_write_to_url_is_an_error() {
+        let url = "http://127.0.0.1/image.png";
+        assert!(encode(Encode::parse_from(["encode", "--in-place", url, "ruSt", "message"]), &Progress::quiet()).is_err());
+        assert!(remove(Remove::parse_from(["remove", "--in-place", url, "ruSt"]), &mut Vec::new()).is_err());
+    }
+
+    // serve 'body' to a single HTTP request on a local port, returning its URL
+    #[cfg(feature = "network")]
+    fn serve_once(body: Vec<u8>) -> (String, std::thread::JoinHandle<()>) {
+        use std::io::Read;
+        use std::net::TcpListener;
+        use std::thread;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/image.png", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+        });
+        (url, server)
+    }
+
+    #[cfg(feature = "network")]
+    #[test]
+    fn test_decode_from_url() {
+        let path = testing_file("decode_url", &[("ruSt", b"served")]);
+        let body = fs::read(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        let (url, server) = serve_once(body);
+
+        let mut out = Vec::new();
+        decode(Decode::parse_from(["decode", &url, "ruSt"]), &mut out).unwrap();
+        server.join().unwrap();
+
+        assert_eq!(out, b"Hidden message: served\n");
+    }
+
+    #[cfg(feature = "network")]
+    #[test]
+    fn test_encode_from_url_with_output() {
+        let path = testing_file("encode_url", &[]);
+        let body = fs::read(&path).unwrap();
+        let (url, server) = serve_once(body);
+        let output = path.with_file_name("encode_url_out.png");
+        let output_str = output.to_str().unwrap();
+
+        encode(Encode::parse_from(["encode", &url, "ruSt", "fetched", "-o", output_str]), &Progress::quiet()).unwrap();
+        server.join().unwrap();
+        let mut out = Vec::new();
+        decode(Decode::parse_from(["decode", output_str, "ruSt"]), &mut out).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        assert_eq!(out, b"Hidden message: fetched\n");
+    }
+
+    // without the network feature the URL still reaches the fetch instead of the filesystem
+    #[cfg(not(feature = "network"))]
+    #[test]
+    fn test_encode_from_url_with_output() {
+        let output = env::temp_dir().join(format!("png_message_{}_encode_url_out.png", process::id()));
+        let err = encode(Encode::parse_from([
+            "encode",
+            "http://127.0.0.1/image.png",
+            "ruSt",
+            "fetched",
+            "-o",
+            output.to_str().unwrap(),
+        ]), &Progress::quiet())
+        .unwrap_err();
+
+        assert!(err.to_string().contains("'network' feature"));
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_encode_backup() {
+        let path = testing_file("encode_backup", &[]);
+        let path_str = path.to_str().unwrap();
+        let original = fs::read(&path).unwrap();
+
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "one", "--backup"]), &Progress::quiet()).unwrap();
+        let backup = fs::read(backup_path(&path)).unwrap();
+        let refused = encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "two", "--backup"]), &Progress::quiet());
+        let after_refused = fs::read(&path).unwrap();
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "two", "--backup", "--force"]), &Progress::quiet()).unwrap();
+        let second_backup = fs::read(backup_path(&path)).unwrap();
+        fs::remove_file(backup_path(&path)).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(backup, original);
+        assert!(refused.is_err());
+        assert_eq!(second_backup, after_refused);
+    }
+
+    #[test]
+    fn test_encode_and_decode_text() {
+        let path = testing_file("encode_text", &[]);
+        let path_str = path.to_str().unwrap();
+        let mut out = Vec::new();
+
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "tEXt", "plain text", "--text", "Comment"]), &Progress::quiet()).unwrap();
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "zTXt", "packed text", "--text", "Title", "--ztext"]), &Progress::quiet()).unwrap();
+        let wrong_type = encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "text", "--text", "Comment"]), &Progress::quiet());
+        let png = Png::open(&path).unwrap();
+        decode(Decode::parse_from(["decode", path_str, "tEXt", "--text"]), &mut out).unwrap();
+        decode(Decode::parse_from(["decode", path_str, "zTXt", "--text"]), &mut out).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(png.chunk_by_type("tEXt").unwrap().data(), b"Comment\0plain text");
+        assert!(wrong_type.is_err());
+        assert_eq!(out, b"Comment: plain text\nTitle: packed text\n");
+    }
+
+    #[test]
+    fn test_dedupe_repeated_encode() {
+        let path = testing_file("dedupe", &[]);
+        let path_str = path.to_str().unwrap();
+
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "message"]), &Progress::quiet()).unwrap();
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "message"]), &Progress::quiet()).unwrap();
+        dedupe(Dedupe::parse_from(["dedupe", "--in-place", path_str])).unwrap();
+        let png = Png::open(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(png.chunks_by_type("ruSt").len(), 1);
+    }
+
+    #[test]
+    fn test_encode_tag() {
+        let path = testing_file("encode_tag", &[]);
+        let path_str = path.to_str().unwrap();
+        let mut out = Vec::new();
+
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "message", "--tag"]), &Progress::quiet()).unwrap();
+        let png = Png::open(&path).unwrap();
+        decode(Decode::parse_from(["decode", path_str, "ruSt", "--show-tag"]), &mut out).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(png.chunk_by_type("ruSt").is_some());
+        assert!(png.chunk_by_type(TAG_CHUNK_TYPE).is_some());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(&format!("Tag: png_message {} encoded at ", env!("CARGO_PKG_VERSION"))));
+        assert!(out.ends_with("Hidden message: message\n"));
+    }
+
+    #[test]
+    fn test_decode_empty_chunk() {
+        let path = testing_file("decode_empty", &[("ruSt", b"")]);
+        let mut out = Vec::new();
+
+        decode(Decode::parse_from(["decode", path.to_str().unwrap(), "ruSt"]), &mut out).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(out, b"(chunk exists but contains no data)\n");
+    }
+
+    #[test]
+    fn test_rename_chunk() {
+        let path = testing_file("rename", &[("ruSt", b"renamed message")]);
+        let path_str = path.to_str().unwrap();
+        let mut out = Vec::new();
+
+        rename_chunk(RenameChunk::parse_from(["rename-chunk", "--in-place", path_str, "ruSt", "teSt"])).unwrap();
+        decode(Decode::parse_from(["decode", path_str, "teSt"]), &mut out).unwrap();
+        let old_gone = decode(Decode::parse_from(["decode", path_str, "ruSt"]), &mut Vec::new());
+        let missing = rename_chunk(RenameChunk::parse_from(["rename-chunk", "--in-place", path_str, "ruSt", "teSt"]));
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(out, b"Hidden message: renamed message\n");
+        assert!(old_gone.is_err());
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn test_decode_ignore_crc() {
+        let path = testing_file("decode_ignore_crc", &[("ruSt", b"still readable")]);
+        let path_str = path.to_str().unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        // corrupt the IHDR CRC
+        bytes[8 + 8 + 13] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+        let mut out = Vec::new();
+
+        let strict = decode(Decode::parse_from(["decode", path_str, "ruSt"]), &mut Vec::new());
+        decode(Decode::parse_from(["decode", path_str, "ruSt", "--ignore-crc"]), &mut out).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(strict.is_err());
+        assert_eq!(out, b"Hidden message: still readable\n");
+    }
+
+    #[test]
+    fn test_list_and_info_ignore_crc() {
+        let path = testing_file("list_ignore_crc", &[("ruSt", b"still readable")]);
+        let path_str = path.to_str().unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        // corrupt the IHDR CRC
+        bytes[8 + 8 + 13] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+        let mut listed = Vec::new();
+
+        let list_strict = list(List::parse_from(["list", path_str]), &mut Vec::new());
+        list(List::parse_from(["list", path_str, "--ignore-crc"]), &mut listed).unwrap();
+        let info_strict = info(Info::parse_from(["info", path_str]), &mut Vec::new());
+        let info_lenient = info(Info::parse_from(["info", path_str, "--ignore-crc"]), &mut Vec::new());
+        fs::remove_file(path).unwrap();
+
+        assert!(list_strict.is_err());
+        assert!(String::from_utf8(listed).unwrap().contains("ruSt"));
+        assert!(info_strict.is_err());
+        assert!(info_lenient.is_ok());
+    }
+
+    #[test]
+    fn test_embed_and_extract_file() {
+        let path = testing_file("embed_file", &[]);
+        let input = env::temp_dir().join(format!("png_message_{}_embed_input.bin", process::id()));
+        let output = env::temp_dir().join(format!("png_message_{}_embed_output.bin", process::id()));
+        let contents: Vec<u8> = (0..10_000).map(|i| (i * 7 % 256) as u8).collect();
+        fs::write(&input, &contents).unwrap();
+
+        embed_file(EmbedFile::parse_from([
+            "embed-file",
+            "--in-place",
+            path.to_str().unwrap(),
+            "fiLe",
+            input.to_str().unwrap(),
+            "--chunk-size",
+            "4096",
+        ]))
+        .unwrap();
+        extract_file(ExtractFile::parse_from([
+            "extract-file",
+            path.to_str().unwrap(),
+            "fiLe",
+            "--output",
+            output.to_str().unwrap(),
+        ]))
+        .unwrap();
+        let extracted = fs::read(&output).unwrap();
+        for file in [path, input, output].iter() {
+            fs::remove_file(file).unwrap();
+        }
+
+        assert_eq!(extracted, contents);
+    }
+
+    #[test]
+    fn test_encode_max_size() {
+        let path = testing_file("encode_max_size", &[]);
+        let original = fs::read(&path).unwrap();
+
+        let result = encode(Encode::parse_from([
+            "encode",
+            "--in-place",
+            path.to_str().unwrap(),
+            "ruSt",
+            "eleven char",
+            "--max-size",
+            "10",
+        ]), &Progress::quiet());
+        let after = fs::read(&path).unwrap();
+        let fits = encode(Encode::parse_from([
+            "encode",
+            "--in-place",
+            path.to_str().unwrap(),
+            "ruSt",
+            "ten chars!",
+            "--max-size",
+            "10",
+        ]), &Progress::quiet());
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.unwrap_err().to_string().contains("more than the maximum"));
+        assert_eq!(after, original);
+        assert!(fits.is_ok());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap_matches_read() {
+        let path = testing_file("mmap", &[("ruSt", b"mapped"), ("teSt", b"")]);
+        let input = read_file(&path).unwrap();
+        let mapped = Png::try_from(input.as_slice()).unwrap();
+        let read = Png::open(&path).unwrap();
+        let is_mapped = matches!(input, Input::Mapped(_));
+        drop(input);
+        fs::remove_file(&path).unwrap();
+
+        assert!(is_mapped);
+        assert_eq!(mapped.chunks(), read.chunks());
+    }
+
+    #[test]
+    fn test_print_color_never() {
+        let path = testing_file("print_color", &[("ruSt", b"hidden")]);
+        let mut never = Vec::new();
+        print(
+            Print::parse_from(["print", path.to_str().unwrap(), "--color", "never"]),
+            &mut never,
+            &Progress::quiet(),
+        )
+        .unwrap();
+        let mut always = Vec::new();
+        print(
+            Print::parse_from(["print", path.to_str().unwrap(), "--color", "always"]),
+            &mut always,
+            &Progress::quiet(),
+        )
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(!never.contains(&0x1b));
+        assert!(String::from_utf8(never).unwrap().contains("Type: ruSt"));
+        assert!(always.contains(&0x1b));
+    }
+
+    #[test]
+    fn test_strip() {
+        let path = testing_file("strip", &[("ruSt", b"hidden"), ("teSt", b"also hidden")]);
+        strip(Strip::parse_from(["strip", "--in-place", path.to_str().unwrap()]), &mut Vec::new(), &Progress::quiet()).unwrap();
+        let png = Png::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "IEND"]);
+    }
+
+    #[test]
+    fn test_unchanged_file_is_still_written_to_output() {
+        let path = testing_file("noop_output", &[("ruSt", b"message")]);
+        let path_str = path.to_str().unwrap();
+        let outputs: Vec<PathBuf> = ["dedupe", "canonicalize", "strip"]
+            .iter()
+            .map(|name| path.with_file_name(format!("png_message_{}_noop_{}.png", process::id(), name)))
+            .collect();
+        let original = fs::read(&path).unwrap();
+
+        dedupe(Dedupe::parse_from(["dedupe", path_str, "-o", outputs[0].to_str().unwrap()])).unwrap();
+        canonicalize(Canonicalize::parse_from(["canonicalize", path_str, "-o", outputs[1].to_str().unwrap()])).unwrap();
+        strip(
+            Strip::parse_from(["strip", path_str, "--keep", "ruSt", "-o", outputs[2].to_str().unwrap()]),
+            &mut Vec::new(),
+            &Progress::quiet(),
+        )
+        .unwrap();
+        let written: Vec<Vec<u8>> = outputs.iter().map(|output| fs::read(output).unwrap()).collect();
+        fs::remove_file(&path).unwrap();
+        for output in &outputs {
+            fs::remove_file(output).unwrap();
+        }
+
+        for bytes in written {
+            assert_eq!(bytes, original);
+        }
+    }
+
+    #[test]
+    fn test_modifying_commands_require_output_or_in_place() {
+        let path = testing_file("modify_in_place", &[("ruSt", b"message"), ("ruSt", b"message")]);
+        let path_str = path.to_str().unwrap();
+        let output = path.with_file_name(format!("png_message_{}_modify_output.png", process::id()));
+        let output_str = output.to_str().unwrap();
+        let original = fs::read(&path).unwrap();
+
+        let refused = [
+            strip(Strip::parse_from(["strip", path_str]), &mut Vec::new(), &Progress::quiet()),
+            dedupe(Dedupe::parse_from(["dedupe", path_str])),
+            canonicalize(Canonicalize::parse_from(["canonicalize", path_str])),
+            rename_chunk(RenameChunk::parse_from(["rename-chunk", path_str, "ruSt", "teSt"])),
+            swap(Swap::parse_from(["swap", path_str, "1", "2"])),
+            convert(Convert::parse_from(["convert", path_str, "ruSt", "teSt"])),
+            copy_chunk(CopyChunk::parse_from(["copy-chunk", path_str, path_str, "ruSt"])),
+            merge(Merge::parse_from(["merge", path_str, path_str])),
+            embed_file(EmbedFile::parse_from(["embed-file", path_str, "fiLe", path_str])),
+        ];
+        dedupe(Dedupe::parse_from(["dedupe", path_str, "-o", output_str])).unwrap();
+        let unchanged = fs::read(&path).unwrap();
+        let deduped = Png::open(&output).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        for result in refused.iter() {
+            assert!(result.as_ref().unwrap_err().to_string().contains("--in-place"));
+        }
+        assert_eq!(unchanged, original);
+        assert_eq!(deduped.chunks_by_type("ruSt").len(), 1);
+    }
+
+    #[test]
+    fn test_encode_requires_output_or_in_place() {
+        let path = testing_file("encode_in_place", &[]);
+        let output = env::temp_dir().join(format!("png_message_{}_encode_output.png", process::id()));
+        let path_str = path.to_str().unwrap();
+        let original = fs::read(&path).unwrap();
+
+        let refused = encode(Encode::parse_from(["encode", path_str, "ruSt", "message"]), &Progress::quiet());
+        let unchanged = fs::read(&path).unwrap();
+        encode(Encode::parse_from(["encode", path_str, "ruSt", "message", "--output", output.to_str().unwrap()]), &Progress::quiet()).unwrap();
+        let written = Png::open(&output).unwrap();
+        let source = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        assert!(refused.unwrap_err().to_string().contains("--in-place"));
+        assert_eq!(unchanged, original);
+        assert_eq!(source, original);
+        assert!(written.chunk_by_type("ruSt").is_some());
+    }
+
+    #[test]
+    fn test_decode_format() {
+        let path = testing_file("decode_format", &[("ruSt", b"ok\x00\xff")]);
+        let decode_as = |format: &str| {
+            let mut out = Vec::new();
+            decode(
+                Decode::parse_from(["decode", path.to_str().unwrap(), "ruSt", "--format", format]),
+                &mut out,
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let outputs: Vec<String> = ["utf8", "hex", "base64", "escape"].iter().map(|format| decode_as(format)).collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(outputs[0], "Hidden message: ok\\x00\\xff\n");
+        assert_eq!(outputs[1], "Hidden message: 6f6b00ff\n");
+        assert_eq!(outputs[2], "Hidden message: b2sA/w==\n");
+        assert_eq!(outputs[3], outputs[0]);
+    }
+
+    #[test]
+    fn test_encode_replace() {
+        let path = testing_file("encode_replace", &[("ruSt", b"old message"), ("teSt", b"after")]);
+        let path_str = path.to_str().unwrap();
+
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "new message", "--replace"]), &Progress::quiet()).unwrap();
+        let replaced = Png::open(&path).unwrap();
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "miSs", "inserted", "--replace"]), &Progress::quiet()).unwrap();
+        let inserted = Png::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let types: Vec<String> = replaced.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "ruSt", "teSt", "IEND"]);
+        assert_eq!(replaced.chunk_by_type("ruSt").unwrap().data(), b"new message");
+        assert!(replaced.chunk_by_type("ruSt").unwrap().has_valid_crc());
+        assert_eq!(inserted.chunks_by_type("miSs").len(), 1);
+        assert_eq!(inserted.chunks_by_type("ruSt").len(), 1);
+    }
+
+    #[test]
+    fn test_public_type_warning() {
+        let warning = public_type_warning(&ChunkType::from_str("gAMA").unwrap()).unwrap();
+        assert!(warning.contains("gaMA"));
+        assert!(public_type_warning(&ChunkType::from_str("ruSt").unwrap()).is_none());
+        assert!(public_type_warning(&ChunkType::from_str("tEXt").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_undo_restores_backup() {
+        let path = testing_file("undo", &[]);
+        let path_str = path.to_str().unwrap();
+        let original = fs::read(&path).unwrap();
+
+        assert!(undo(Undo::parse_from(["undo", path_str])).is_err());
+        encode(Encode::parse_from(["encode", "--in-place", "--backup", path_str, "ruSt", "message"]), &Progress::quiet()).unwrap();
+        assert_ne!(fs::read(&path).unwrap(), original);
+        undo(Undo::parse_from(["undo", path_str, "--discard"])).unwrap();
+        let restored = fs::read(&path).unwrap();
+        let backup_left = backup_path(&path).exists();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(restored, original);
+        assert!(!backup_left);
+    }
+
+    #[test]
+    fn test_encode_force_ascii() {
+        let path = testing_file("encode_force_ascii", &[]);
+        let path_str = path.to_str().unwrap();
+
+        encode(Encode::parse_from(["encode", "--in-place", "--force-ascii", path_str, "ruSt", "a\0b\x07c\nd é"]), &Progress::quiet()).unwrap();
+        let png = Png::open(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"a?b?c\nd ??");
+    }
+
+    #[test]
+    fn test_encode_from_file_normalize_newlines() {
+        let path = testing_file("encode_crlf", &[]);
+        let message = env::temp_dir().join(format!("png_message_{}_crlf.txt", process::id()));
+        fs::write(&message, b"first\r\nsecond\r\nlone\r").unwrap();
+        let path_str = path.to_str().unwrap();
+        let message_str = message.to_str().unwrap();
+
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "asIs", "--from-file", message_str]), &Progress::quiet()).unwrap();
+        encode(Encode::parse_from([
+            "encode",
+            "--in-place",
+            path_str,
+            "noRm",
+            "--from-file",
+            message_str,
+            "--normalize-newlines",
+        ]), &Progress::quiet())
+        .unwrap();
+        let png = Png::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&message).unwrap();
+
+        assert_eq!(png.chunk_by_type("asIs").unwrap().data(), b"first\r\nsecond\r\nlone\r");
+        assert_eq!(png.chunk_by_type("noRm").unwrap().data(), b"first\nsecond\nlone\r");
+    }
+
+    #[test]
+    fn test_verify_reports_crc_mismatch() {
+        let path = testing_file("verify", &[("ruSt", b"message")]);
+        let path_str = path.to_str().unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        let computed = testing_chunk("ruSt", b"message").crc();
+        // the ruSt CRC follows the signature, IHDR and the chunk's own length, type and data
+        let crc_offset = 8 + 25 + 8 + 7;
+        bytes[crc_offset..crc_offset + 4].copy_from_slice(&0xdeadbeefu32.to_be_bytes());
+        fs::write(&path, bytes).unwrap();
+        let mut out = Vec::new();
+
+        let result = verify(Verify::parse_from(["verify", path_str, "ruSt"]), &mut out);
+        let mut valid = Vec::new();
+        verify(Verify::parse_from(["verify", path_str, "IHDR"]), &mut valid).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("Stored CRC:   deadbeef\nComputed CRC: {:08x}\nCRC mismatch\n", computed)
+        );
+        assert!(String::from_utf8(valid).unwrap().ends_with("CRC matches\n"));
+    }
+
+    #[test]
+    fn test_encode_recursive() {
+        let root = env::temp_dir().join(format!("png_message_{}_recursive", process::id()));
+        let deeper = root.join("sub").join("deeper");
+        fs::create_dir_all(&deeper).unwrap();
+        let pngs = [root.join("a.png"), root.join("sub").join("b.PNG"), deeper.join("c.png")];
+        let image = fs::read(testing_file("recursive_source", &[])).unwrap();
+        for png in pngs.iter() {
+            fs::write(png, &image).unwrap();
+        }
+        fs::write(root.join("sub").join("notes.txt"), b"not a png").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, deeper.join("loop")).unwrap();
+
+        encode(Encode::parse_from([
+            "encode",
+            "--in-place",
+            "--recursive",
+            root.to_str().unwrap(),
+            "ruSt",
+            "batch message",
+        ]), &Progress::quiet())
+        .unwrap();
+        let encoded: Vec<Png> = pngs.iter().map(|png| Png::open(png).unwrap()).collect();
+        let notes = fs::read(root.join("sub").join("notes.txt")).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_file(env::temp_dir().join(format!("png_message_{}_recursive_source.png", process::id()))).unwrap();
+
+        for png in encoded {
+            assert_eq!(png.chunks_by_type("ruSt").len(), 1);
+        }
+        assert_eq!(notes, b"not a png");
+    }
+
+    #[test]
+    fn test_encode_recursive_threads() {
+        let root = env::temp_dir().join(format!("png_message_{}_threads", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let pngs: Vec<PathBuf> = (0..6).map(|index| root.join(format!("{}.png", index))).collect();
+        let image = fs::read(testing_file("threads_source", &[])).unwrap();
+        for png in &pngs {
+            fs::write(png, &image).unwrap();
+        }
+
+        encode(Encode::parse_from([
+            "encode",
+            "--in-place",
+            "--recursive",
+            "--threads",
+            "4",
+            root.to_str().unwrap(),
+            "ruSt",
+            "batch message",
+        ]), &Progress::quiet())
+        .unwrap();
+        let encoded: Vec<Png> = pngs.iter().map(|png| Png::open(png).unwrap()).collect();
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_file(env::temp_dir().join(format!("png_message_{}_threads_source.png", process::id()))).unwrap();
+
+        for png in encoded {
+            assert_eq!(png.chunks_by_type("ruSt")[0].data(), b"batch message");
+        }
+    }
+
+    #[test]
+    fn test_threads_requires_recursive() {
+        assert!(Encode::try_parse_from(["encode", "--in-place", "--threads", "4", "a.png", "ruSt", "message"]).is_err());
+        assert!(Strip::try_parse_from(["strip", "--in-place", "--threads", "4", "a.png"]).is_err());
+        assert!(Encode::try_parse_from(["encode", "--in-place", "a.png", "ruSt", "message"]).is_ok());
+        assert!(Strip::try_parse_from(["strip", "--in-place", "--recursive", "--threads", "4", "dir"]).is_ok());
+    }
+
+    #[test]
+    fn test_strip_recursive_threads() {
+        let root = env::temp_dir().join(format!("png_message_{}_strip_threads", process::id()));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        let pngs: Vec<PathBuf> = (0..6).map(|index| root.join("sub").join(format!("{}.png", index))).collect();
+        let source = testing_file("strip_threads_source", &[("ruSt", b"hidden"), ("tEXt", b"Comment\0hi")]);
+        let image = fs::read(&source).unwrap();
+        fs::remove_file(source).unwrap();
+        for png in &pngs {
+            fs::write(png, &image).unwrap();
+        }
+        let mut out = Vec::new();
+
+        strip(
+            Strip::parse_from([
+                "strip",
+                "--in-place",
+                "--recursive",
+                "--threads",
+                "4",
+                root.to_str().unwrap(),
+                "--keep",
+                "tEXt",
+            ]),
+            &mut out,
+            &Progress::quiet(),
+        )
+        .unwrap();
+        let stripped: Vec<Png> = pngs.iter().map(|png| Png::open(png).unwrap()).collect();
+        fs::remove_dir_all(&root).unwrap();
+
+        for png in stripped {
+            let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+            assert_eq!(types, ["IHDR", "tEXt", "IEND"]);
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 6);
+        for png in &pngs {
+            assert!(out.contains(&format!("{}: Removed 1 ancillary chunk(s)\n", png.display())));
+        }
+    }
+
+    #[test]
+    fn test_run_parallel_keeps_order() {
+        let items: Vec<usize> = (0..10).collect();
+        assert_eq!(run_parallel(&items, 4, |item| item * 2), (0..20).step_by(2).collect::<Vec<_>>());
+        assert_eq!(run_parallel(&items, 1, |item| item * 2), (0..20).step_by(2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_encode_recursive_resumes_from_manifest() {
+        let root = env::temp_dir().join(format!("png_message_{}_manifest", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let manifest = env::temp_dir().join(format!("png_message_{}_manifest.txt", process::id()));
+        let (done, pending) = (root.join("a.png"), root.join("b.png"));
+        let image = fs::read(testing_file("manifest_source", &[])).unwrap();
+        fs::write(&done, &image).unwrap();
+        fs::write(&pending, &image).unwrap();
+        // the interrupted run finished a.png before stopping
+        fs::write(&manifest, format!("{}\n", done.display())).unwrap();
+
+        encode(Encode::parse_from([
+            "encode",
+            "--in-place",
+            "--recursive",
+            "--manifest",
+            manifest.to_str().unwrap(),
+            root.to_str().unwrap(),
+            "ruSt",
+            "batch message",
+        ]), &Progress::quiet())
+        .unwrap();
+        let (done_png, pending_png) = (Png::open(&done).unwrap(), Png::open(&pending).unwrap());
+        let recorded = fs::read_to_string(&manifest).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_file(&manifest).unwrap();
+        fs::remove_file(env::temp_dir().join(format!("png_message_{}_manifest_source.png", process::id()))).unwrap();
+
+        assert!(done_png.chunks_by_type("ruSt").is_empty());
+        assert_eq!(pending_png.chunks_by_type("ruSt").len(), 1);
+        assert_eq!(recorded, format!("{}\n{}\n", done.display(), pending.display()));
+    }
+
+    #[test]
+    fn test_info() {
+        let path = testing_file("info", &[]);
+        let mut out = Vec::new();
+        info(Info::parse_from(["info", path.to_str().unwrap()]), &mut out).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Width: 1\nHeight: 1\nBit depth: 8\nColor type: 6 (RGB and alpha)\nCompression: 0\nFilter: 0\nInterlace: none\n"
+        );
+    }
+
+    #[test]
+    fn test_info_json() {
+        let path = testing_file("info_json", &[("ruSt", b"message")]);
+        let mut out = Vec::new();
+        info(Info::parse_from(["info", path.to_str().unwrap(), "--json"]), &mut out).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let summary: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(summary["width"], 1);
+        assert_eq!(summary["height"], 1);
+        assert_eq!(summary["bit_depth"], 8);
+        assert_eq!(summary["color_type"], 6);
+        assert_eq!(summary["interlace"], 0);
+        assert_eq!(summary["chunk_count"], 3);
+        // 4 samples at 1 bit each don't fill a byte
+        assert_eq!(summary["lsb_capacity_bytes"], 0);
+    }
+
+    #[test]
+    fn test_json_pretty() {
+        let path = testing_file("json_pretty", &[]);
+        let path_str = path.to_str().unwrap();
+        let mut compact = Vec::new();
+        let mut pretty = Vec::new();
+
+        info(Info::parse_from(["info", path_str, "--json"]), &mut compact).unwrap();
+        info(Info::parse_from(["info", path_str, "--json", "--pretty"]), &mut pretty).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(compact.iter().filter(|&&byte| byte == b'\n').count(), 1);
+        assert!(pretty.iter().filter(|&&byte| byte == b'\n').count() > 1);
+        let compact: serde_json::Value = serde_json::from_slice(&compact).unwrap();
+        let pretty: serde_json::Value = serde_json::from_slice(&pretty).unwrap();
+        assert_eq!(compact, pretty);
+    }
+
+    #[test]
+    fn test_remove_and_strip_dry_run() {
+        let path = testing_file("dry_run", &[("ruSt", b"hidden"), ("teSt", b"also hidden")]);
+        let path_str = path.to_str().unwrap();
+        let original = fs::read(&path).unwrap();
+        let mut removed = Vec::new();
+        let mut stripped = Vec::new();
+
+        remove(Remove::parse_from(["remove", path_str, "teSt", "--dry-run"]), &mut removed).unwrap();
+        strip(Strip::parse_from(["strip", path_str, "--dry-run"]), &mut stripped, &Progress::quiet()).unwrap();
+        let after = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(after, original);
+        assert_eq!(removed, b"Would remove 1 chunk(s): teSt\n");
+        assert_eq!(stripped, b"Would remove 2 chunk(s): ruSt, teSt\n");
+    }
+
+    #[test]
+    fn test_validate_json_missing_iend() {
+        let path = testing_file("validate_json", &[("ruSt", b"message")]);
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 12]).unwrap();
+        let mut out = Vec::new();
+
+        let result = validate(Validate::parse_from(["validate", path.to_str().unwrap(), "--json"]), &mut out);
+        fs::remove_file(&path).unwrap();
+
+        let report: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert!(result.is_err());
+        assert_eq!(report["valid"], false);
+        let kinds: Vec<&str> = report["failures"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|failure| failure["kind"].as_str().unwrap())
+            .collect();
+        assert!(kinds.contains(&"missing_iend"));
+    }
+
+    #[test]
+    fn test_swap() {
+        let path = testing_file("swap", &[("frSt", b"first"), ("seCd", b"second")]);
+        let path_str = path.to_str().unwrap();
+
+        swap(Swap::parse_from(["swap", "--in-place", path_str, "1", "2"])).unwrap();
+        let swapped = fs::read(&path).unwrap();
+        let refused = swap(Swap::parse_from(["swap", "--in-place", path_str, "0", "1"]));
+        let out_of_range = swap(Swap::parse_from(["swap", "--in-place", path_str, "1", "4"]));
+        fs::remove_file(&path).unwrap();
+
+        let expected: Vec<u8> = [137, 80, 78, 71, 13, 10, 26, 10]
+            .iter()
+            .copied()
+            .chain(testing_chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]).as_bytes())
+            .chain(testing_chunk("seCd", b"second").as_bytes())
+            .chain(testing_chunk("frSt", b"first").as_bytes())
+            .chain(testing_chunk("IEND", &[]).as_bytes())
+            .collect();
+        assert_eq!(swapped, expected);
+        assert!(refused.is_err());
+        assert!(out_of_range.is_err());
+    }
+
+    #[test]
+    fn test_decode_trailing_data() {
+        let path = testing_file("trailing", &[("ruSt", b"message")]);
+        let path_str = path.to_str().unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.extend_from_slice(b"appended by another tool");
+        fs::write(&path, bytes).unwrap();
+        let mut out = Vec::new();
+
+        decode(Decode::parse_from(["decode", path_str, "ruSt"]), &mut out).unwrap();
+        let strict = decode(Decode::parse_from(["decode", path_str, "ruSt", "--strict"]), &mut Vec::new());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(out, b"Hidden message: message\n");
+        assert!(strict.unwrap_err().to_string().contains("24 bytes of trailing data"));
+    }
+
+    #[test]
+    fn test_decode_chunk_after_iend() {
+        let path = testing_file("decode_after_iend", &[]);
+        let path_str = path.to_str().unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.extend_from_slice(&testing_chunk("ruSt", b"old message").as_bytes());
+        fs::write(&path, bytes).unwrap();
+        let mut out = Vec::new();
+
+        let default = decode(Decode::parse_from(["decode", path_str, "ruSt"]), &mut Vec::new());
+        decode(Decode::parse_from(["decode", path_str, "ruSt", "--after-iend"]), &mut out).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(default.is_err());
+        assert_eq!(out, b"Hidden message: old message\n");
+    }
+
+    #[test]
+    fn test_scan_trailing() {
+        let path = testing_file("scan_trailing", &[]);
+        let dump = env::temp_dir().join(format!("png_message_{}_scan_trailing.bin", process::id()));
+        let payload: &[u8] = b"\x00payload after IEND\xff";
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.extend_from_slice(payload);
+        fs::write(&path, bytes).unwrap();
+        let mut out = Vec::new();
+
+        scan_trailing(ScanTrailing::parse_from(["scan-trailing", path.to_str().unwrap()]), &mut out).unwrap();
+        scan_trailing(
+            ScanTrailing::parse_from(["scan-trailing", path.to_str().unwrap(), "--to-file", dump.to_str().unwrap()]),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let dumped = fs::read(&dump).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&dump).unwrap();
+
+        assert_eq!(dumped, payload);
+        assert_eq!(out, b"20 bytes after IEND:\n\\x00payload after IEND\\xff\n");
+    }
+
+    #[test]
+    fn test_decode_text_keyword() {
+        let path = testing_file(
+            "decode_keyword",
+            &[("tEXt", b"Author\0someone"), ("tEXt", b"Comment\0hidden here"), ("tEXt", b"Description\0a picture")],
+        );
+        let path_str = path.to_str().unwrap();
+        let mut out = Vec::new();
+
+        decode(Decode::parse_from(["decode", path_str, "tEXt", "--text", "--keyword", "Comment"]), &mut out).unwrap();
+        let missing = decode(
+            Decode::parse_from(["decode", path_str, "tEXt", "--text", "--keyword", "Title"]),
+            &mut Vec::new(),
+        );
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(out, b"Comment: hidden here\n");
+        assert_eq!(
+            missing.unwrap_err().to_string(),
+            "No tEXt chunk has keyword Title, found: Author, Comment, Description"
+        );
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let path = testing_file("ignore_case", &[("ruSt", b"message")]);
+        let path_str = path.to_str().unwrap();
+        let mut out = Vec::new();
+
+        let exact = decode(Decode::parse_from(["decode", path_str, "rust"]), &mut Vec::new());
+        decode(Decode::parse_from(["decode", path_str, "rust", "--ignore-case"]), &mut out).unwrap();
+        remove(Remove::parse_from(["remove", "--in-place", path_str, "RUST", "--ignore-case"]), &mut Vec::new()).unwrap();
+        let png = Png::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(exact.is_err());
+        assert_eq!(out, b"Hidden message: message\n");
+        assert!(png.chunk_by_type("ruSt").is_none());
+    }
+
+    #[test]
+    fn test_encoding_utf16le() {
+        let path = testing_file("encoding", &[]);
+        let path_str = path.to_str().unwrap();
+        let mut out = Vec::new();
+
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "héllo wörld ✓", "--encoding", "utf-16le"]), &Progress::quiet())
+            .unwrap();
+        let png = Png::open(&path).unwrap();
+        decode(Decode::parse_from(["decode", path_str, "ruSt", "--encoding", "UTF-16LE"]), &mut out).unwrap();
+        let unknown = decode(Decode::parse_from(["decode", path_str, "ruSt", "--encoding", "klingon"]), &mut Vec::new());
+        fs::remove_file(&path).unwrap();
+
+        let stored: Vec<u8> = "héllo wörld ✓".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), stored.as_slice());
+        assert_eq!(String::from_utf8(out).unwrap(), "Hidden message: héllo wörld ✓\n");
+        assert!(unknown.is_err());
+    }
+
+    #[test]
+    fn test_encode_after() {
+        let path = testing_file("encode_after", &[("teSt", b"existing")]);
+        let path_str = path.to_str().unwrap();
+
+        encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "early", "--after", "IHDR"]), &Progress::quiet()).unwrap();
+        let missing = encode(Encode::parse_from(["encode", "--in-place", path_str, "ruSt", "x", "--after", "tIME"]), &Progress::quiet());
+        let png = Png::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "ruSt", "teSt", "IEND"]);
+        assert!(missing.unwrap_err().to_string().contains("tIME"));
+    }
+
+    #[test]
+    fn test_list_columns() {
+        let path = testing_file("list", &[("ruSt", b"message")]);
+        let path_str = path.to_str().unwrap();
+        let mut minimal = Vec::new();
+        let mut full = Vec::new();
+
+        list(List::parse_from(["list", path_str]), &mut minimal).unwrap();
+        list(List::parse_from(["list", path_str, "--show-crc", "--show-offset"]), &mut full).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let ihdr_crc = testing_chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]).crc();
+        let rust_crc = testing_chunk("ruSt", b"message").crc();
+        let iend_crc = testing_chunk("IEND", &[]).crc();
+        assert_eq!(
+            String::from_utf8(minimal).unwrap(),
+            "   0 IHDR         13\n   1 ruSt          7\n   2 IEND          0\n"
+        );
+        assert_eq!(
+            String::from_utf8(full).unwrap(),
+            format!(
+                "   0 IHDR         13          8 {:08x}\n   1 ruSt          7         33 {:08x}\n   2 IEND          0         52 {:08x}\n",
+                ihdr_crc, rust_crc, iend_crc
+            )
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let first = testing_file("merge_first", &[("ruSt", b"one"), ("teSt", b"skipped")]);
+        let second = testing_file("merge_second", &[("ruSt", b"two")]);
+        let destination = testing_file("merge_destination", &[]);
+        let mut out = Vec::new();
+
+        merge(Merge::parse_from([
+            "merge",
+            "--in-place",
+            destination.to_str().unwrap(),
+            first.to_str().unwrap(),
+            second.to_str().unwrap(),
+            "--type",
+            "ruSt",
+        ]))
+        .unwrap();
+        decode(Decode::parse_from(["decode", destination.to_str().unwrap(), "ruSt", "--all"]), &mut out).unwrap();
+        let png = Png::open(&destination).unwrap();
+        for path in [first, second, destination].iter() {
+            fs::remove_file(path).unwrap();
+        }
+
+        assert_eq!(out, b"Hidden message: one\nHidden message: two\n");
+        assert!(png.chunk_by_type("teSt").is_none());
+        assert_eq!(png.chunks_by_type("IHDR").len(), 1);
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+    }
+}
@@ -1,27 +1,75 @@
 use std::convert::TryFrom;
+use std::collections::hash_map::DefaultHasher;
 use std::fmt;
-use std::io::{BufReader, Read};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, Read, Write};
 
 use crc::crc32;
 
 use crate::chunk_type::ChunkType;
+use crate::error::PngError;
 use crate::{Error, Result};
 
-#[derive(Debug)]
+/// A single PNG chunk: a type and its data. The length and CRC are derived from those,
+/// unless the chunk was built with `new_with_crc` or parsed leniently, in which case the
+/// given CRC is kept until the type or data is changed.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use std::str::FromStr;
+/// use png_message::chunk::Chunk;
+/// use png_message::chunk_type::ChunkType;
+///
+/// let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hello".to_vec());
+/// let parsed = Chunk::try_from(chunk.as_bytes().as_slice()).unwrap();
+///
+/// assert_eq!(parsed.length(), 5);
+/// assert_eq!(parsed.chunk_type().to_string(), "ruSt");
+/// assert_eq!(parsed.as_bytes(), chunk.as_bytes());
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Chunk {
-    pub typee: ChunkType,
-    pub data: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(rename = "chunk_type"))]
+    typee: ChunkType,
+    data: Vec<u8>,
+    // a forced CRC is not serialized, a deserialized chunk always computes its CRC
+    #[cfg_attr(feature = "serde", serde(skip))]
+    forced_crc: Option<u32>,
 }
 
 impl Chunk {
     pub fn new(typee: ChunkType, data: Vec<u8>) -> Self {
-        Self { typee, data }
+        Self {
+            typee,
+            data,
+            forced_crc: None,
+        }
+    }
+
+    /// Build a chunk that reports and serializes `crc` instead of the real checksum.
+    ///
+    /// This is for testing and for generating malformed files, parsing the
+    /// serialized chunk with `try_from` will fail unless `crc` happens to be correct.
+    pub fn new_with_crc(typee: ChunkType, data: Vec<u8>, crc: u32) -> Self {
+        Self {
+            typee,
+            data,
+            forced_crc: Some(crc),
+        }
     }
 
-    fn length(&self) -> u32 {
+    /// Number of bytes in the chunk data, not counting the length, type and CRC fields.
+    pub fn length(&self) -> u32 {
         self.data.len() as u32
     }
 
+    /// Size of the chunk in a file: the data plus 12 bytes for the length, type and CRC fields.
+    pub fn total_size(&self) -> usize {
+        self.data.len() + 12
+    }
+
+    /// The 4 byte chunk type.
     pub fn chunk_type(&self) -> &ChunkType {
         &self.typee
     }
@@ -30,21 +78,70 @@ impl Chunk {
         self.data.as_ref()
     }
 
+    /// Replace the type. A forced CRC is dropped, the CRC is computed from the new type.
+    pub fn set_chunk_type(&mut self, chunk_type: ChunkType) {
+        self.typee = chunk_type;
+        self.forced_crc = None;
+    }
+
+    /// Replace the data. A forced CRC is dropped, the CRC is computed from the new data.
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.data = data;
+        self.forced_crc = None;
+    }
+
     // CRC calculated on preeceding bytes in the chunk (chunk type and data)
     // this is always present even if there is no data.
     // used to verify each chunk for corrupted data
     pub fn crc(&self) -> u32 {
+        self.forced_crc.unwrap_or_else(|| self.computed_crc())
+    }
+
+    /// Whether the CRC matches the type and data, only chunks built with a
+    /// forced CRC or parsed leniently can fail this.
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc() == self.computed_crc()
+    }
+
+    /// Hash of the type and data, equal chunks have equal hashes within a run of the program.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The CRC calculated from the type and data, which differs from `crc` when
+    /// the chunk was built or parsed with a wrong one.
+    pub fn computed_crc(&self) -> u32 {
         crc32::checksum_ieee(&[&self.typee.name, self.data.as_slice()].concat())
     }
 
+    /// The data as UTF-8, failing with the offset of the first byte that isn't.
     pub fn data_as_string(&self) -> Result<String> {
         let string = String::from_utf8(self.data.clone());
         match string {
             Ok(s) => Ok(s),
-            Err(e) => Err(Box::new(e)),
+            Err(e) => Err(format!(
+                "Chunk data is not valid UTF-8 from byte {}: {}",
+                e.utf8_error().valid_up_to(),
+                e
+            )
+            .into()),
         }
     }
 
+    /// Number of characters in the data when it is valid UTF-8, which is less than the
+    /// byte count for text with multi-byte characters.
+    pub fn data_char_count(&self) -> Option<usize> {
+        std::str::from_utf8(&self.data).ok().map(|text| text.chars().count())
+    }
+
+    /// The data as UTF-8, with invalid sequences replaced by U+FFFD.
+    pub fn data_as_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.data).into_owned()
+    }
+
+    /// The chunk as it is stored in a file: length, type, data and CRC.
     pub fn as_bytes(&self) -> Vec<u8> {
         self.length()
             .to_be_bytes()
@@ -55,12 +152,77 @@ impl Chunk {
             .chain(self.crc().to_be_bytes().iter().cloned())
             .collect()
     }
+
+    /// Write the chunk as it is stored in a file, without building it in memory first.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.length().to_be_bytes())?;
+        w.write_all(&self.typee.name)?;
+        w.write_all(&self.data)?;
+        w.write_all(&self.crc().to_be_bytes())
+    }
 }
 
-impl TryFrom<&[u8]> for Chunk {
-    type Error = Error;
+/// Options for `Chunk::try_from_opts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkParseOptions {
+    /// Fail when the stored CRC doesn't match. When off the stored CRC is kept
+    /// on the chunk, check it with `has_valid_crc`.
+    pub verify_crc: bool,
+}
 
-    fn try_from(bytes: &[u8]) -> Result<Self> {
+impl Default for ChunkParseOptions {
+    fn default() -> Self {
+        ChunkParseOptions { verify_crc: true }
+    }
+}
+
+impl Chunk {
+    pub fn try_from_opts(bytes: &[u8], options: ChunkParseOptions) -> Result<Self> {
+        let (chunk, received_crc) = Chunk::read_fields(bytes)?;
+
+        if chunk.crc() == received_crc {
+            Ok(chunk)
+        } else if options.verify_crc {
+            Err(PngError::InvalidChunk.into())
+        } else {
+            Ok(Chunk::new_with_crc(chunk.typee, chunk.data, received_crc))
+        }
+    }
+
+    /// Parse a chunk like `try_from`, but keep it even if its CRC doesn't match.
+    /// The stored CRC is kept, check it with `has_valid_crc`.
+    pub fn try_from_lenient(bytes: &[u8]) -> Result<Self> {
+        Chunk::try_from_opts(bytes, ChunkParseOptions { verify_crc: false })
+    }
+
+    /// Read a single chunk from `reader`, failing like `try_from` if its CRC doesn't match.
+    pub fn from_reader<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut buffer: [u8; 4] = [0; 4];
+
+        reader.read_exact(&mut buffer)?;
+        let data_length = u32::from_be_bytes(buffer);
+
+        reader.read_exact(&mut buffer)?;
+        let chunk_type = ChunkType::try_from(buffer)?;
+
+        // read through 'take' so a bogus length can't allocate more than the reader holds
+        let mut data = Vec::new();
+        reader.take(data_length as u64).read_to_end(&mut data)?;
+        if data.len() != data_length as usize {
+            return Err(PngError::TruncatedChunk.into());
+        }
+
+        reader.read_exact(&mut buffer)?;
+        let chunk = Chunk::new(chunk_type, data);
+        if chunk.crc() == u32::from_be_bytes(buffer) {
+            Ok(chunk)
+        } else {
+            Err(PngError::InvalidChunk.into())
+        }
+    }
+
+    // the chunk and the CRC stored after it
+    fn read_fields(bytes: &[u8]) -> Result<(Chunk, u32)> {
         let mut reader = BufReader::new(bytes);
         let mut buffer: [u8; 4] = [0; 4];
 
@@ -69,9 +231,14 @@ impl TryFrom<&[u8]> for Chunk {
         let data_length = u32::from_be_bytes(buffer);
 
         // get next 4 bytes to determine chunk type
-        reader.read_exact(&mut buffer).unwrap();
+        reader.read_exact(&mut buffer)?;
         let chunk_type = ChunkType::try_from(buffer)?;
 
+        // check the declared length before allocating, a crafted length field could ask for 4GB
+        let remaining = bytes.len().saturating_sub(8);
+        if data_length as usize > remaining.saturating_sub(4) {
+            return Err(PngError::TruncatedChunk.into());
+        }
         let mut data_buffer = vec![0; data_length as usize];
         reader.read_exact(&mut data_buffer)?;
         let chunk_data = data_buffer;
@@ -79,16 +246,51 @@ impl TryFrom<&[u8]> for Chunk {
         reader.read_exact(&mut buffer)?;
         let received_crc = u32::from_be_bytes(buffer);
 
-        let chunk = Chunk {
-            typee: chunk_type,
-            data: chunk_data,
-        };
+        Ok((Chunk::new(chunk_type, chunk_data), received_crc))
+    }
+}
 
-        if chunk.crc() == received_crc {
-            Ok(chunk)
-        } else {
-            Err("Invalid chunk".into())
-        }
+impl TryFrom<&[u8]> for Chunk {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        Chunk::try_from_opts(bytes, ChunkParseOptions::default())
+    }
+}
+
+impl TryFrom<Vec<u8>> for Chunk {
+    type Error = Error;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self> {
+        Chunk::try_from(bytes.as_slice())
+    }
+}
+
+impl From<&Chunk> for Vec<u8> {
+    fn from(chunk: &Chunk) -> Self {
+        chunk.as_bytes()
+    }
+}
+
+impl From<Chunk> for Vec<u8> {
+    fn from(chunk: Chunk) -> Self {
+        chunk.as_bytes()
+    }
+}
+
+// chunks are equal when their type and data are, the length and CRC are derived from those
+impl PartialEq for Chunk {
+    fn eq(&self, other: &Self) -> bool {
+        self.typee == other.typee && self.data == other.data
+    }
+}
+
+impl Eq for Chunk {}
+
+impl Hash for Chunk {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.typee.name.hash(state);
+        self.data.hash(state);
     }
 }
 
@@ -105,9 +307,30 @@ impl fmt::Display for Chunk {
     }
 }
 
+// chunks with any 4 letter type and up to 256 bytes of data, shrinking toward short data
+#[cfg(test)]
+pub(crate) fn arb_chunk() -> impl proptest::strategy::Strategy<Value = Chunk> {
+    use proptest::prelude::*;
+
+    ("[a-zA-Z]{4}", prop::collection::vec(any::<u8>(), 0..256)).prop_map(|(chunk_type, data)| {
+        Chunk::new(ChunkType::try_from(<[u8; 4]>::try_from(chunk_type.as_bytes()).unwrap()).unwrap(), data)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn test_chunk_round_trip(chunk in arb_chunk()) {
+            let parsed = Chunk::try_from(chunk.as_bytes().as_slice()).unwrap();
+            prop_assert_eq!(&parsed, &chunk);
+            prop_assert_eq!(parsed.crc(), chunk.crc());
+            prop_assert!(parsed.has_valid_crc());
+        }
+    }
 
     fn testing_chunk() -> Chunk {
         let data_length: u32 = 42;
@@ -133,6 +356,12 @@ mod tests {
         assert_eq!(chunk.length(), 42);
     }
 
+    #[test]
+    fn test_chunk_total_size() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.total_size(), chunk.as_bytes().len());
+    }
+
     #[test]
     fn test_chunk_type() {
         let chunk = testing_chunk();
@@ -147,6 +376,25 @@ mod tests {
         assert_eq!(chunk_string, expected_chunk_string);
     }
 
+    #[test]
+    fn test_chunk_string_truncated_sequence() {
+        // "héllo" cut in the middle of the two byte é
+        let chunk = Chunk::new(ChunkType::try_from(*b"RuSt").unwrap(), vec![b'h', 0xc3]);
+        let err = chunk.data_as_string().unwrap_err();
+        assert!(err.to_string().contains("from byte 1"));
+        assert_eq!(chunk.data_as_string_lossy(), "h\u{fffd}");
+    }
+
+    #[test]
+    fn test_chunk_char_count() {
+        let chunk = Chunk::new(ChunkType::try_from(*b"RuSt").unwrap(), "héllo ✓".as_bytes().to_vec());
+        assert_eq!(chunk.data().len(), 10);
+        assert_eq!(chunk.data_char_count(), Some(7));
+
+        let binary = Chunk::new(ChunkType::try_from(*b"RuSt").unwrap(), vec![b'h', 0xc3]);
+        assert_eq!(binary.data_char_count(), None);
+    }
+
     #[test]
     fn test_chunk_crc() {
         let chunk = testing_chunk();
@@ -201,6 +449,159 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_length_field_overstates_data() {
+        let message_bytes = "This is where your secret message will be!".as_bytes();
+        for data_length in [100u32, u32::MAX].iter() {
+            let chunk_data: Vec<u8> = data_length
+                .to_be_bytes()
+                .iter()
+                .chain(b"RuSt".iter())
+                .chain(message_bytes.iter())
+                .chain(2882656334u32.to_be_bytes().iter())
+                .copied()
+                .collect();
+
+            let err = Chunk::try_from(chunk_data.as_ref()).unwrap_err();
+            assert_eq!(err.downcast_ref::<PngError>(), Some(&PngError::TruncatedChunk));
+            assert!(Chunk::try_from_lenient(chunk_data.as_ref()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_write_to_and_from_reader() {
+        let chunks = [
+            testing_chunk(),
+            Chunk::new(ChunkType::try_from(*b"IEND").unwrap(), Vec::new()),
+        ];
+        let mut buffer = Vec::new();
+        for chunk in chunks.iter() {
+            chunk.write_to(&mut buffer).unwrap();
+        }
+        assert_eq!(buffer, [chunks[0].as_bytes(), chunks[1].as_bytes()].concat());
+
+        let mut reader = buffer.as_slice();
+        assert_eq!(Chunk::from_reader(&mut reader).unwrap(), chunks[0]);
+        assert_eq!(Chunk::from_reader(&mut reader).unwrap(), chunks[1]);
+        assert!(Chunk::from_reader(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_from_reader_truncated_data() {
+        let bytes = testing_chunk().as_bytes();
+        let err = Chunk::from_reader(&mut &bytes[..20]).unwrap_err();
+        assert_eq!(err.downcast_ref::<PngError>(), Some(&PngError::TruncatedChunk));
+    }
+
+    #[test]
+    fn test_try_from_opts_bad_crc() {
+        let bytes = Chunk::new_with_crc(ChunkType::try_from(*b"RuSt").unwrap(), b"data".to_vec(), 1).as_bytes();
+
+        let strict = Chunk::try_from_opts(&bytes, ChunkParseOptions { verify_crc: true });
+        let lenient = Chunk::try_from_opts(&bytes, ChunkParseOptions { verify_crc: false }).unwrap();
+
+        assert_eq!(strict.unwrap_err().downcast_ref::<PngError>(), Some(&PngError::InvalidChunk));
+        assert_eq!(lenient.crc(), 1);
+        assert!(!lenient.has_valid_crc());
+        assert_eq!(lenient.data(), b"data");
+    }
+
+    #[test]
+    fn test_vec_conversions() {
+        let chunk = testing_chunk();
+
+        let borrowed: Vec<u8> = Vec::from(&chunk);
+        assert_eq!(borrowed, chunk.as_bytes());
+        assert_eq!(Chunk::try_from(borrowed).unwrap(), chunk);
+
+        let owned: Vec<u8> = chunk.clone().into();
+        assert_eq!(Chunk::try_from(owned).unwrap(), chunk);
+        assert!(Chunk::try_from(vec![0, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_chunk_truncated_in_type_field() {
+        let bytes = [0, 0, 0, 0, b'R', b'u'];
+        assert!(Chunk::try_from(&bytes[..]).is_err());
+        assert!(Chunk::try_from_lenient(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_empty_chunk_round_trip() {
+        let chunk = Chunk::new(ChunkType::try_from(*b"IEND").unwrap(), Vec::new());
+        let bytes = chunk.as_bytes();
+        assert_eq!(bytes.len(), 12);
+
+        let parsed = Chunk::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(parsed.length(), 0);
+        assert!(parsed.data().is_empty());
+        assert_eq!(parsed.crc(), chunk.crc());
+    }
+
+    #[test]
+    fn test_chunk_equality() {
+        let chunk_type = ChunkType::try_from(*b"RuSt").unwrap();
+        let a = Chunk::new(chunk_type, b"message".to_vec());
+        let b = Chunk::new(chunk_type, b"message".to_vec());
+        let c = Chunk::new(chunk_type, b"other message".to_vec());
+
+        assert_eq!(a, b);
+        assert_eq!(a.content_hash(), b.content_hash());
+        assert_ne!(a, c);
+        assert_ne!(a, Chunk::new(ChunkType::try_from(*b"RuSx").unwrap(), b"message".to_vec()));
+    }
+
+    #[test]
+    fn test_new_with_crc() {
+        let chunk_type = ChunkType::try_from(*b"RuSt").unwrap();
+        let data = b"This is where your secret message will be!".to_vec();
+        let chunk = Chunk::new_with_crc(chunk_type, data.clone(), 1234);
+
+        assert_eq!(chunk.crc(), 1234);
+        assert_eq!(&chunk.as_bytes()[chunk.as_bytes().len() - 4..], 1234u32.to_be_bytes());
+        assert!(Chunk::try_from(chunk.as_bytes().as_ref()).is_err());
+
+        let chunk = Chunk::new_with_crc(chunk_type, data, 2882656334);
+        assert!(Chunk::try_from(chunk.as_bytes().as_ref()).is_ok());
+    }
+
+    #[test]
+    fn test_mutation_drops_forced_crc() {
+        let chunk_type = ChunkType::try_from(*b"RuSt").unwrap();
+        let mut chunk = Chunk::new_with_crc(chunk_type, b"data".to_vec(), 1234);
+        chunk.set_data(b"new data".to_vec());
+        assert!(chunk.has_valid_crc());
+        assert!(Chunk::try_from(chunk.as_bytes().as_ref()).is_ok());
+
+        let mut chunk = Chunk::new_with_crc(chunk_type, b"data".to_vec(), 1234);
+        chunk.set_chunk_type(ChunkType::try_from(*b"teSt").unwrap());
+        assert!(chunk.has_valid_crc());
+        assert_eq!(chunk.chunk_type().to_string(), "teSt");
+    }
+
+    #[test]
+    fn test_lenient_chunk_from_bytes() {
+        let data_length: u32 = 42;
+        let chunk_type = "RuSt".as_bytes();
+        let message_bytes = "This is where your secret message will be!".as_bytes();
+        let crc: u32 = 2882656333;
+
+        let chunk_data: Vec<u8> = data_length
+            .to_be_bytes()
+            .iter()
+            .chain(chunk_type.iter())
+            .chain(message_bytes.iter())
+            .chain(crc.to_be_bytes().iter())
+            .copied()
+            .collect();
+
+        let chunk = Chunk::try_from_lenient(chunk_data.as_ref()).unwrap();
+        assert_eq!(chunk.crc(), 2882656333);
+        assert!(!chunk.has_valid_crc());
+        assert_eq!(chunk.as_bytes(), chunk_data);
+        assert!(testing_chunk().has_valid_crc());
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -221,4 +622,23 @@ mod tests {
 
         let _chunk_string = format!("{}", chunk);
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_chunk_json_round_trip() {
+        let chunk = testing_chunk();
+        let json = serde_json::to_string(&chunk).unwrap();
+        let parsed: Chunk = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.chunk_type(), chunk.chunk_type());
+        assert_eq!(parsed.data(), chunk.data());
+        assert_eq!(parsed.crc(), 2882656334);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_chunk_json_invalid_type() {
+        let json = r#"{"chunk_type":"Ru1t","data":[]}"#;
+        assert!(serde_json::from_str::<Chunk>(json).is_err());
+    }
 }
@@ -1,4 +1,4 @@
-use std::convert::TryFrom;
+use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use std::str;
 
@@ -18,36 +18,69 @@ impl ChunkType {
         self.name
     }
 
-    fn is_valid(&self) -> bool {
+    pub fn is_valid(&self) -> bool {
         self.is_reserved_bit_valid()
             && self.name[0].is_ascii()
             && self.name[1].is_ascii()
             && self.name[3].is_ascii()
     }
 
-    fn is_critical(&self) -> bool {
+    pub fn is_critical(&self) -> bool {
         // chunks that are not strictly necessary to the display the content of the file
         // is a "ancillary" chunk
         // chunks that are necessary to the display the contents of the file is a "critical" chunk
         self.name[0].is_ascii_uppercase()
     }
 
-    fn is_public(&self) -> bool {
+    pub fn is_ancillary(&self) -> bool {
+        !self.is_critical()
+    }
+
+    pub fn is_public(&self) -> bool {
         // public chunk is one that is part of the PNG specification
         // private chunk is our own defined chunk for our own purpose
         self.name[1].is_ascii_uppercase()
     }
 
-    fn is_reserved_bit_valid(&self) -> bool {
+    pub fn is_private(&self) -> bool {
+        !self.is_public()
+    }
+
+    pub fn is_reserved_bit_valid(&self) -> bool {
         // must be 0 in files conforming to the 1.2 version of the PNG spec
         self.name[2].is_ascii_uppercase()
     }
 
-    fn is_safe_to_copy(&self) -> bool {
+    pub fn is_safe_to_copy(&self) -> bool {
         // if chunk's safe-to-copy bit is 1, chunk may be copied to a modifed PNG file
         // if chunk's safe-to-copy bit is 0, the chunk depend on the image data
         self.name[3].is_ascii_lowercase()
     }
+
+    // the property bits are bit 5 of each byte, i.e. the case of the letter
+    pub fn set_critical(&mut self, critical: bool) {
+        self.name[0] = if critical {
+            self.name[0].to_ascii_uppercase()
+        } else {
+            self.name[0].to_ascii_lowercase()
+        };
+    }
+
+    pub fn set_public(&mut self, public: bool) {
+        self.name[1] = if public {
+            self.name[1].to_ascii_uppercase()
+        } else {
+            self.name[1].to_ascii_lowercase()
+        };
+    }
+
+    pub fn set_safe_to_copy(&mut self, safe_to_copy: bool) {
+        self.name[3] = if safe_to_copy {
+            self.name[3].to_ascii_lowercase()
+        } else {
+            self.name[3].to_ascii_uppercase()
+        };
+    }
 }
 
 // TryFrom does simple and safe type conversions.
@@ -60,11 +93,30 @@ impl TryFrom<[u8; 4]> for ChunkType {
     }
 }
 
+// a slice has to be exactly 4 ASCII letters, like the names FromStr accepts
+impl TryFrom<&[u8]> for ChunkType {
+    type Error = &'static str;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let name: [u8; 4] = value
+            .try_into()
+            .map_err(|_| "chunk type must be exactly 4 ASCII letters")?;
+        if !name.iter().all(u8::is_ascii_alphabetic) {
+            return Err("chunk type must be exactly 4 ASCII letters");
+        }
+        ChunkType::try_from(name)
+    }
+}
+
 // FromStr does parsing of a value through a string
 impl str::FromStr for ChunkType {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 4 {
+            return Err("chunk type must be exactly 4 ASCII letters");
+        }
+
         let mut type_name: [u8; 4] = [0; 4];
 
         for (index, character) in s.chars().enumerate() {
@@ -75,14 +127,34 @@ impl str::FromStr for ChunkType {
             }
         }
 
-        Ok(ChunkType::try_from(type_name))?
+        ChunkType::try_from(type_name)
     }
 }
 
 // Display formats value using a given formatter
+// try_from accepts any 4 bytes, so bytes that aren't printable ASCII are shown escaped as \xNN
 impl fmt::Display for ChunkType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", std::str::from_utf8(&self.bytes()).unwrap_or("Could not print chunk type"))
+        for &byte in self.bytes().iter() {
+            write!(f, "{}", std::ascii::escape_default(byte))?;
+        }
+        Ok(())
+    }
+}
+
+// serialized as the 4 character type name, parsed back through FromStr so invalid names are rejected
+#[cfg(feature = "serde")]
+impl serde::Serialize for ChunkType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ChunkType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        str::FromStr::from_str(&name).map_err(serde::de::Error::custom)
     }
 }
 
@@ -100,6 +172,14 @@ mod tests {
         assert_eq!(expected, actual.bytes());
     }
 
+    #[test]
+    pub fn test_chunk_type_from_slice() {
+        let expected = ChunkType::try_from([82, 117, 83, 116]).unwrap();
+        assert_eq!(ChunkType::try_from(&b"RuSt"[..]).unwrap(), expected);
+        assert!(ChunkType::try_from(&b"RuS"[..]).is_err());
+        assert!(ChunkType::try_from(&b"Ru5t"[..]).is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_from_str() {
         let expected = ChunkType::try_from([82, 117, 83, 116]).unwrap();
@@ -107,6 +187,13 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    pub fn test_chunk_type_from_str_length() {
+        assert!(ChunkType::from_str("RuS").is_err());
+        assert!(ChunkType::from_str("RuSt").is_ok());
+        assert!(ChunkType::from_str("RuStX").is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_is_critical() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -131,6 +218,20 @@ mod tests {
         assert!(!chunk.is_public());
     }
 
+    #[test]
+    pub fn test_chunk_type_is_ancillary() {
+        assert!(ChunkType::from_str("ruSt").unwrap().is_ancillary());
+        assert!(ChunkType::from_str("tEXt").unwrap().is_ancillary());
+        assert!(!ChunkType::from_str("IHDR").unwrap().is_ancillary());
+    }
+
+    #[test]
+    pub fn test_chunk_type_is_private() {
+        assert!(ChunkType::from_str("RuSt").unwrap().is_private());
+        assert!(!ChunkType::from_str("tEXt").unwrap().is_private());
+        assert!(!ChunkType::from_str("IDAT").unwrap().is_private());
+    }
+
     #[test]
     pub fn test_chunk_type_is_reserved_bit_valid() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -155,6 +256,39 @@ mod tests {
         assert!(!chunk.is_safe_to_copy());
     }
 
+    #[test]
+    pub fn test_chunk_type_set_critical() {
+        let mut chunk = ChunkType::from_str("RuSt").unwrap();
+        chunk.set_critical(false);
+        assert!(!chunk.is_critical());
+        assert_eq!(&chunk.to_string(), "ruSt");
+        chunk.set_critical(true);
+        assert!(chunk.is_critical());
+        assert_eq!(&chunk.to_string(), "RuSt");
+    }
+
+    #[test]
+    pub fn test_chunk_type_set_public() {
+        let mut chunk = ChunkType::from_str("RuSt").unwrap();
+        chunk.set_public(true);
+        assert!(chunk.is_public());
+        assert_eq!(&chunk.to_string(), "RUSt");
+        chunk.set_public(false);
+        assert!(!chunk.is_public());
+        assert_eq!(&chunk.to_string(), "RuSt");
+    }
+
+    #[test]
+    pub fn test_chunk_type_set_safe_to_copy() {
+        let mut chunk = ChunkType::from_str("RuSt").unwrap();
+        chunk.set_safe_to_copy(false);
+        assert!(!chunk.is_safe_to_copy());
+        assert_eq!(&chunk.to_string(), "RuST");
+        chunk.set_safe_to_copy(true);
+        assert!(chunk.is_safe_to_copy());
+        assert_eq!(&chunk.to_string(), "RuSt");
+    }
+
     #[test]
     pub fn test_valid_chunk_is_valid() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -176,6 +310,12 @@ mod tests {
         assert_eq!(&chunk.to_string(), "RuSt");
     }
 
+    #[test]
+    pub fn test_chunk_type_string_escapes_non_ascii() {
+        let chunk = ChunkType::try_from([0xff, 0, b'S', b't']).unwrap();
+        assert_eq!(&chunk.to_string(), "\\xff\\x00St");
+    }
+
     #[test]
     pub fn test_chunk_type_trait_impls() {
         let chunk_type_1: ChunkType = TryFrom::try_from([82, 117, 83, 116]).unwrap();
@@ -0,0 +1,94 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+
+use png_message::chunk::Chunk;
+use png_message::Error;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const CYAN: &str = "\x1b[36m";
+const YELLOW: &str = "\x1b[33m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    // 'auto' colors only a terminal stdout, and never when NO_COLOR is set
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal(),
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("Unknown color choice '{}', expected auto, always or never", s).into()),
+        }
+    }
+}
+
+fn paint(text: &str, style: &str, enabled: bool) -> String {
+    if enabled {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+// same layout as Chunk's Display, with the type bold and colored by criticality
+// and the data size highlighted when the data reads as text
+pub fn format_chunk(chunk: &Chunk, enabled: bool) -> String {
+    let chunk_type = chunk.chunk_type();
+    let type_style = if chunk_type.is_critical() {
+        format!("{}{}", BOLD, RED)
+    } else {
+        format!("{}{}", BOLD, CYAN)
+    };
+    let data_bytes = format!("Data bytes: {}", chunk.data().len());
+    let data_bytes = if is_printable(chunk.data()) {
+        paint(&data_bytes, YELLOW, enabled)
+    } else {
+        data_bytes
+    };
+
+    format!(
+        "Length: {}\nType: {}\n{}\nCRC: {}\n",
+        chunk.length(),
+        paint(&chunk_type.to_string(), &type_style, enabled),
+        data_bytes,
+        chunk.crc()
+    )
+}
+
+fn is_printable(data: &[u8]) -> bool {
+    !data.is_empty()
+        && std::str::from_utf8(data).is_ok_and(|text| text.chars().all(|c| !c.is_control() || c.is_whitespace()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use png_message::chunk_type::ChunkType;
+
+    #[test]
+    fn test_format_chunk_matches_display_without_color() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hidden".to_vec());
+        assert_eq!(format_chunk(&chunk, false), chunk.to_string());
+        assert!(format_chunk(&chunk, true).contains("\x1b[1m\x1b[36mruSt\x1b[0m"));
+    }
+}
@@ -1,11 +1,18 @@
 use std::path::PathBuf;
 
 use clap::Clap;
+use regex::Regex;
 
-use crate::chunk_type::ChunkType;
+use png_message::chunk_type::ChunkType;
+use png_message::render::Format;
+
+use crate::color::ColorChoice;
 
 #[derive(Clap)]
 pub struct CmdOptions {
+    /// Show informational messages such as parsing progress
+    #[clap(short, long, global = true)]
+    pub verbose: bool,
     #[clap(subcommand)]
     pub sub_command: Commands,
 }
@@ -16,28 +23,600 @@ pub enum Commands {
     Decode(Decode),
     Remove(Remove),
     Print(Print),
+    List(List),
+    Search(Search),
+    Repl(Repl),
+    CopyChunk(CopyChunk),
+    Merge(Merge),
+    Diff(Diff),
+    Capacity(Capacity),
+    Info(Info),
+    Dedupe(Dedupe),
+    Strip(Strip),
+    Verify(Verify),
+    Validate(Validate),
+    RenameChunk(RenameChunk),
+    Swap(Swap),
+    ScanTrailing(ScanTrailing),
+    EmbedFile(EmbedFile),
+    ExtractFile(ExtractFile),
+    Convert(Convert),
+    Hash(Hash),
+    Undo(Undo),
+    Canonicalize(Canonicalize),
 }
 
 #[derive(Clap, Debug)]
 pub struct Encode {
     pub file_path: PathBuf,
     pub chunk_type: ChunkType,
-    pub message: String,
+    #[clap(required_unless_present = "from-file")]
+    pub message: Option<String>,
+    /// Read the message from PATH instead of the command line
+    #[clap(long, conflicts_with = "message")]
+    pub from_file: Option<PathBuf>,
+    /// Convert CRLF line endings in the message to LF
+    #[clap(long)]
+    pub normalize_newlines: bool,
+    /// Replace control characters and non-ASCII bytes in the message with '?', keeping newlines and tabs
+    #[clap(long)]
+    pub force_ascii: bool,
+    /// Store the message in the text encoding NAME, e.g. latin1, utf-16le or shift_jis
+    #[clap(long, conflicts_with = "text")]
+    pub encoding: Option<String>,
+    /// Split the message across chunks of at most SIZE bytes
+    #[clap(long)]
+    pub split: Option<usize>,
+    /// Insert the message right after the first chunk of type TYPE instead of at the end
+    #[clap(long)]
+    pub after: Option<ChunkType>,
+    /// Overwrite the first chunk of CHUNK_TYPE instead of adding another one, if there is one
+    #[clap(long, conflicts_with = "split")]
+    pub replace: bool,
+    /// Skip writing if a chunk of CHUNK_TYPE with the same data already exists, so reruns don't pile up copies
+    #[clap(long)]
+    pub if_absent: bool,
+    /// Refuse to encode if the stored chunk data would exceed BYTES
+    #[clap(long)]
+    pub max_size: Option<usize>,
+    /// Store the message as a standard tEXt chunk under KEYWORD
+    #[clap(long)]
+    pub text: Option<String>,
+    /// Compress the text, storing it as a zTXt chunk (requires --text) or a compressed iTXt chunk
+    #[clap(long)]
+    pub ztext: bool,
+    /// Store the message as UTF-8 in an iTXt chunk under KEYWORD
+    #[clap(long, conflicts_with_all = &["text", "encoding"])]
+    pub itext: Option<String>,
+    /// Language tag of the iTXt chunk, e.g. en or ja-JP
+    #[clap(long, requires = "itext")]
+    pub language: Option<String>,
+    /// Also write a pmTg chunk recording the tool version and time of encoding
+    #[clap(long)]
+    pub tag: bool,
+    /// Write the result to PATH, leaving FILE_PATH untouched
+    #[clap(short, long, conflicts_with = "in-place")]
+    pub output: Option<PathBuf>,
+    /// Overwrite FILE_PATH with the result. Earlier versions did this by default,
+    /// scripts relying on that need to pass this flag
+    #[clap(long)]
+    pub in_place: bool,
+    /// Encode every .png file under the directory FILE_PATH (requires --in-place)
+    #[clap(long, conflicts_with = "output")]
+    pub recursive: bool,
+    /// With --recursive, skip the files listed in PATH and add each newly encoded file to it,
+    /// so an interrupted run can be resumed
+    #[clap(long, requires = "recursive")]
+    pub manifest: Option<PathBuf>,
+    /// With --recursive, encode up to N files at once, one at a time by default
+    #[clap(long, requires = "recursive")]
+    pub threads: Option<usize>,
+    /// Copy the original file to <FILE_PATH>.bak before writing
+    #[clap(long)]
+    pub backup: bool,
+    /// Encode even under a critical chunk type, skip the public type warning, and overwrite an existing backup
+    #[clap(long)]
+    pub force: bool,
 }
 
 #[derive(Clap, Debug)]
 pub struct Decode {
     pub file_path: PathBuf,
-    pub chunk_type: ChunkType,
+    #[clap(required_unless_present = "type-regex")]
+    pub chunk_type: Option<ChunkType>,
+    /// Decode the first chunk type in the file that matches the regular expression PATTERN
+    /// instead of CHUNK_TYPE. It matches anywhere in the type unless anchored with ^ and $
+    #[clap(long)]
+    pub type_regex: Option<Regex>,
+    /// Match CHUNK_TYPE ignoring case. The case of each letter encodes whether a chunk is
+    /// critical, private and safe to copy, so those properties are ignored too
+    #[clap(long)]
+    pub ignore_case: bool,
+    /// Write only the message bytes to stdout, without a label or newline
+    #[clap(long)]
+    pub raw: bool,
+    /// Don't end each message with a newline, for payloads that must be byte-exact
+    #[clap(long)]
+    pub no_newline: bool,
+    /// Also write the message bytes, unchanged, to PATH
+    #[clap(long, conflicts_with = "text")]
+    pub tee: Option<PathBuf>,
+    /// Print at most N matching chunks
+    #[clap(long, default_value = "1")]
+    pub count: usize,
+    /// Print every matching chunk
+    #[clap(long)]
+    pub all: bool,
+    /// Show the message as utf8, hex, base64 or escape (utf8 with \xNN for non-printable bytes)
+    #[clap(long, default_value = "utf8")]
+    pub format: Format,
+    /// Read the message as text in the encoding NAME, taking precedence over --format
+    #[clap(long)]
+    pub encoding: Option<String>,
+    /// Parse the chunks as tEXt/zTXt and print their keyword and text
+    #[clap(long)]
+    pub text: bool,
+    /// Parse the chunks as iTXt and print their keyword, language, translated keyword and text
+    #[clap(long, conflicts_with = "text")]
+    pub itext: bool,
+    /// Only print text chunks with KEYWORD (requires --text)
+    #[clap(long, requires = "text")]
+    pub keyword: Option<String>,
+    /// Also print the pmTg chunk written by encode --tag
+    #[clap(long)]
+    pub show_tag: bool,
+    /// Keep chunks with a bad CRC instead of failing, reporting them as warnings
+    #[clap(long)]
+    pub ignore_crc: bool,
+    /// Fail on any bytes after IEND instead of ignoring them
+    #[clap(long)]
+    pub strict: bool,
+    /// Also read chunks after IEND, where older versions of encode put messages
+    #[clap(long)]
+    pub after_iend: bool,
 }
 
 #[derive(Clap, Debug)]
 pub struct Remove {
     pub file_path: PathBuf,
     pub chunk_type: ChunkType,
+    /// Match CHUNK_TYPE ignoring case. The case of each letter encodes whether a chunk is
+    /// critical, private and safe to copy, so those properties are ignored too
+    #[clap(long)]
+    pub ignore_case: bool,
+    /// Show which chunks would be removed without writing anything
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Write the result to PATH, leaving FILE_PATH untouched
+    #[clap(short, long, conflicts_with = "in-place")]
+    pub output: Option<PathBuf>,
+    /// Overwrite FILE_PATH with the result. Earlier versions did this by default,
+    /// scripts relying on that need to pass this flag
+    #[clap(long)]
+    pub in_place: bool,
+    /// Copy the original file to <FILE_PATH>.bak before writing
+    #[clap(long)]
+    pub backup: bool,
+    /// Overwrite an existing backup
+    #[clap(long)]
+    pub force: bool,
 }
 
 #[derive(Clap, Debug)]
 pub struct Print {
     pub file_path: PathBuf,
+    /// Show the byte offset where each chunk begins
+    #[clap(long)]
+    pub offsets: bool,
+    /// Keep chunks with a bad CRC instead of failing, reporting them as warnings
+    #[clap(long)]
+    pub ignore_crc: bool,
+    /// Fail on any bytes after IEND instead of ignoring them
+    #[clap(long)]
+    pub strict: bool,
+    /// Color critical and ancillary chunks: auto, always or never
+    #[clap(long, default_value = "auto")]
+    pub color: ColorChoice,
+    /// Show up to N bytes of each chunk's data, with non-printable bytes as \xNN
+    #[clap(long)]
+    pub preview: Option<usize>,
+    /// Also show the number of characters in chunks whose data is valid UTF-8
+    #[clap(long)]
+    pub chars: bool,
+    /// Only include chunks whose type matches the regular expression PATTERN, e.g. ^[a-z] for
+    /// ancillary chunks. It matches anywhere in the type unless anchored with ^ and $
+    #[clap(long)]
+    pub type_regex: Option<Regex>,
+    /// Leave out chunks of TYPE, may be given more than once
+    #[clap(long)]
+    pub exclude: Vec<ChunkType>,
+}
+
+#[derive(Clap, Debug)]
+pub struct List {
+    pub file_path: PathBuf,
+    /// Add a column with each chunk's CRC
+    #[clap(long)]
+    pub show_crc: bool,
+    /// Add a column with the byte offset where each chunk begins
+    #[clap(long)]
+    pub show_offset: bool,
+    /// Only include chunks whose type matches the regular expression PATTERN, e.g. ^[a-z] for
+    /// ancillary chunks. It matches anywhere in the type unless anchored with ^ and $
+    #[clap(long)]
+    pub type_regex: Option<Regex>,
+    /// Only include chunks with at least BYTES of data
+    #[clap(long)]
+    pub min_length: Option<usize>,
+    /// Only include chunks with at most BYTES of data
+    #[clap(long)]
+    pub max_length: Option<usize>,
+    /// Leave out chunks of TYPE, may be given more than once
+    #[clap(long)]
+    pub exclude: Vec<ChunkType>,
+    /// Keep chunks with a bad CRC instead of failing, reporting them as warnings
+    #[clap(long)]
+    pub ignore_crc: bool,
+    /// Fail on any bytes after IEND instead of ignoring them
+    #[clap(long)]
+    pub strict: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Search {
+    pub file_path: PathBuf,
+    pub query: String,
+    /// Treat the query as a hex byte pattern, e.g. "deadbeef"
+    #[clap(long)]
+    pub bytes: bool,
+    /// Only print how many chunks contain the query, exiting with 1 if none do
+    #[clap(long)]
+    pub count_only: bool,
+    /// Only include chunks whose type matches the regular expression PATTERN, e.g. ^[a-z] for
+    /// ancillary chunks. It matches anywhere in the type unless anchored with ^ and $
+    #[clap(long)]
+    pub type_regex: Option<Regex>,
+    /// Match the query, unless it is --bytes, and --type-regex ignoring case. The case of each
+    /// letter of a chunk type encodes whether it is critical, private and safe to copy, so
+    /// those properties are ignored too
+    #[clap(short, long)]
+    pub ignore_case: bool,
+    /// Only include chunks with at least BYTES of data
+    #[clap(long)]
+    pub min_length: Option<usize>,
+    /// Only include chunks with at most BYTES of data
+    #[clap(long)]
+    pub max_length: Option<usize>,
+    /// Keep chunks with a bad CRC instead of failing, reporting them as warnings
+    #[clap(long)]
+    pub ignore_crc: bool,
+    /// Fail on any bytes after IEND instead of ignoring them
+    #[clap(long)]
+    pub strict: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Repl {
+    pub file_path: PathBuf,
+    /// Make save write to PATH, leaving FILE_PATH untouched
+    #[clap(short, long, conflicts_with = "in-place")]
+    pub output: Option<PathBuf>,
+    /// Make save overwrite FILE_PATH
+    #[clap(long)]
+    pub in_place: bool,
+    /// Copy the original file to <FILE_PATH>.bak before saving
+    #[clap(long)]
+    pub backup: bool,
+    /// Overwrite an existing backup
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct CopyChunk {
+    pub source_path: PathBuf,
+    pub destination_path: PathBuf,
+    pub chunk_type: ChunkType,
+    /// Write the result to PATH, leaving DESTINATION_PATH untouched
+    #[clap(short, long, conflicts_with = "in-place")]
+    pub output: Option<PathBuf>,
+    /// Overwrite DESTINATION_PATH with the result. Earlier versions did this by default,
+    /// scripts relying on that need to pass this flag
+    #[clap(long)]
+    pub in_place: bool,
+    /// Copy the original destination to <DESTINATION_PATH>.bak before writing
+    #[clap(long)]
+    pub backup: bool,
+    /// Overwrite an existing backup
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Merge {
+    /// The PNG the chunks are added to
+    pub destination_path: PathBuf,
+    /// PNGs to take chunks from
+    #[clap(required = true)]
+    pub source_paths: Vec<PathBuf>,
+    /// Only merge chunks of TYPE, may be given more than once. Defaults to every ancillary chunk
+    #[clap(long = "type")]
+    pub types: Vec<ChunkType>,
+    /// Write the result to PATH, leaving DESTINATION_PATH untouched
+    #[clap(short, long, conflicts_with = "in-place")]
+    pub output: Option<PathBuf>,
+    /// Overwrite DESTINATION_PATH with the result. Earlier versions did this by default,
+    /// scripts relying on that need to pass this flag
+    #[clap(long)]
+    pub in_place: bool,
+    /// Copy the original destination to <DESTINATION_PATH>.bak before writing
+    #[clap(long)]
+    pub backup: bool,
+    /// Overwrite an existing backup
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Diff {
+    pub original_path: PathBuf,
+    pub other_path: PathBuf,
+    /// Keep chunks with a bad CRC instead of failing, reporting them as warnings
+    #[clap(long)]
+    pub ignore_crc: bool,
+    /// Fail on any bytes after IEND instead of ignoring them
+    #[clap(long)]
+    pub strict: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Capacity {
+    pub file_path: PathBuf,
+    /// Keep chunks with a bad CRC instead of failing, reporting them as warnings
+    #[clap(long)]
+    pub ignore_crc: bool,
+    /// Fail on any bytes after IEND instead of ignoring them
+    #[clap(long)]
+    pub strict: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Info {
+    pub file_path: PathBuf,
+    /// Print the header fields, chunk count and 1-bit LSB capacity as a JSON object
+    #[clap(long)]
+    pub json: bool,
+    /// Indent the JSON over several lines instead of printing it on one
+    #[clap(long, requires = "json")]
+    pub pretty: bool,
+    /// Keep chunks with a bad CRC instead of failing, reporting them as warnings
+    #[clap(long)]
+    pub ignore_crc: bool,
+    /// Fail on any bytes after IEND instead of ignoring them
+    #[clap(long)]
+    pub strict: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Canonicalize {
+    pub file_path: PathBuf,
+    /// Write the result to PATH, leaving FILE_PATH untouched
+    #[clap(short, long, conflicts_with = "in-place")]
+    pub output: Option<PathBuf>,
+    /// Overwrite FILE_PATH with the result. Earlier versions did this by default,
+    /// scripts relying on that need to pass this flag
+    #[clap(long)]
+    pub in_place: bool,
+    /// Copy the original file to <FILE_PATH>.bak before writing
+    #[clap(long)]
+    pub backup: bool,
+    /// Overwrite an existing backup
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Dedupe {
+    pub file_path: PathBuf,
+    /// Write the result to PATH, leaving FILE_PATH untouched
+    #[clap(short, long, conflicts_with = "in-place")]
+    pub output: Option<PathBuf>,
+    /// Overwrite FILE_PATH with the result. Earlier versions did this by default,
+    /// scripts relying on that need to pass this flag
+    #[clap(long)]
+    pub in_place: bool,
+    /// Copy the original file to <FILE_PATH>.bak before writing
+    #[clap(long)]
+    pub backup: bool,
+    /// Overwrite an existing backup
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Strip {
+    pub file_path: PathBuf,
+    /// Show which chunks would be removed without writing anything
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Keep ancillary chunks of TYPE, may be given more than once
+    #[clap(long)]
+    pub keep: Vec<ChunkType>,
+    /// Strip ancillary chunks of TYPE even if --keep lists them, may be given more than once
+    #[clap(long)]
+    pub exclude: Vec<ChunkType>,
+    /// Strip every .png file under the directory FILE_PATH (requires --in-place)
+    #[clap(long, conflicts_with = "output")]
+    pub recursive: bool,
+    /// With --recursive, strip up to N files at once, one at a time by default
+    #[clap(long, requires = "recursive")]
+    pub threads: Option<usize>,
+    /// Write the result to PATH, leaving FILE_PATH untouched
+    #[clap(short, long, conflicts_with = "in-place")]
+    pub output: Option<PathBuf>,
+    /// Overwrite FILE_PATH with the result. Earlier versions did this by default,
+    /// scripts relying on that need to pass this flag
+    #[clap(long)]
+    pub in_place: bool,
+    /// Copy the original file to <FILE_PATH>.bak before writing
+    #[clap(long)]
+    pub backup: bool,
+    /// Overwrite an existing backup
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Verify {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+}
+
+#[derive(Clap, Debug)]
+pub struct Validate {
+    pub file_path: PathBuf,
+    /// Print the result as a JSON object with `valid` and a list of `failures`
+    #[clap(long)]
+    pub json: bool,
+    /// Indent the JSON over several lines instead of printing it on one
+    #[clap(long, requires = "json")]
+    pub pretty: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct RenameChunk {
+    pub file_path: PathBuf,
+    pub old_type: ChunkType,
+    pub new_type: ChunkType,
+    /// Write the result to PATH, leaving FILE_PATH untouched
+    #[clap(short, long, conflicts_with = "in-place")]
+    pub output: Option<PathBuf>,
+    /// Overwrite FILE_PATH with the result. Earlier versions did this by default,
+    /// scripts relying on that need to pass this flag
+    #[clap(long)]
+    pub in_place: bool,
+    /// Copy the original file to <FILE_PATH>.bak before writing
+    #[clap(long)]
+    pub backup: bool,
+    /// Rename to a critical chunk type, and overwrite an existing backup
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct EmbedFile {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+    pub input_path: PathBuf,
+    /// Maximum number of file bytes stored in each chunk
+    #[clap(long, default_value = "65536")]
+    pub chunk_size: usize,
+    /// Write the result to PATH, leaving FILE_PATH untouched
+    #[clap(short, long, conflicts_with = "in-place")]
+    pub output: Option<PathBuf>,
+    /// Overwrite FILE_PATH with the result. Earlier versions did this by default,
+    /// scripts relying on that need to pass this flag
+    #[clap(long)]
+    pub in_place: bool,
+    /// Copy the original file to <FILE_PATH>.bak before writing
+    #[clap(long)]
+    pub backup: bool,
+    /// Embed even under a critical chunk type, and overwrite an existing backup
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct ExtractFile {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+    /// Where to write the file, defaults to its original name in the current directory
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+    /// Keep chunks with a bad CRC instead of failing, reporting them as warnings
+    #[clap(long)]
+    pub ignore_crc: bool,
+    /// Fail on any bytes after IEND instead of ignoring them
+    #[clap(long)]
+    pub strict: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Convert {
+    pub file_path: PathBuf,
+    /// Type of the chunk holding the message, tEXt and zTXt chunks are read as text
+    pub chunk_type: ChunkType,
+    /// Type to store the message as, tEXt and zTXt need --keyword
+    pub new_type: ChunkType,
+    /// Keyword of the text chunk to write
+    #[clap(long)]
+    pub keyword: Option<String>,
+    /// Write the result to PATH, leaving FILE_PATH untouched
+    #[clap(short, long, conflicts_with = "in-place")]
+    pub output: Option<PathBuf>,
+    /// Overwrite FILE_PATH with the result. Earlier versions did this by default,
+    /// scripts relying on that need to pass this flag
+    #[clap(long)]
+    pub in_place: bool,
+    /// Copy the original file to <FILE_PATH>.bak before writing
+    #[clap(long)]
+    pub backup: bool,
+    /// Convert to a critical chunk type, and overwrite an existing backup
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Hash {
+    pub file_path: PathBuf,
+    /// Hash only the signature and critical chunks, which stay the same when hidden chunks are added
+    #[clap(long)]
+    pub critical_only: bool,
+    /// Keep chunks with a bad CRC instead of failing, reporting them as warnings
+    #[clap(long, requires = "critical-only")]
+    pub ignore_crc: bool,
+    /// Fail on any bytes after IEND instead of ignoring them
+    #[clap(long, requires = "critical-only")]
+    pub strict: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Undo {
+    pub file_path: PathBuf,
+    /// Delete <FILE_PATH>.bak once it is restored
+    #[clap(long)]
+    pub discard: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Swap {
+    pub file_path: PathBuf,
+    /// Index of the first chunk, counting from 0 in file order
+    pub first: usize,
+    /// Index of the second chunk
+    pub second: usize,
+    /// Write the result to PATH, leaving FILE_PATH untouched
+    #[clap(short, long, conflicts_with = "in-place")]
+    pub output: Option<PathBuf>,
+    /// Overwrite FILE_PATH with the result. Earlier versions did this by default,
+    /// scripts relying on that need to pass this flag
+    #[clap(long)]
+    pub in_place: bool,
+    /// Copy the original file to <FILE_PATH>.bak before writing
+    #[clap(long)]
+    pub backup: bool,
+    /// Allow moving IHDR from the front or IEND from the end, and overwrite an existing backup
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct ScanTrailing {
+    pub file_path: PathBuf,
+    /// Write the bytes after IEND to PATH instead of printing them
+    #[clap(long)]
+    pub to_file: Option<PathBuf>,
+    /// Keep chunks with a bad CRC instead of failing, reporting them as warnings
+    #[clap(long)]
+    pub ignore_crc: bool,
 }
@@ -0,0 +1,117 @@
+use std::fmt;
+
+use crate::chunk::Chunk;
+
+// how a chunk of the original file compares to the other file
+#[derive(Debug)]
+pub enum ChunkDiff<'a> {
+    Same(&'a Chunk),
+    Changed(&'a Chunk, &'a Chunk),
+    Added(&'a Chunk),
+    Removed(&'a Chunk),
+}
+
+// compare two chunk lists, pairing chunks up along the longest common sequence of chunk
+// types so a chunk inserted in the middle shows up as one addition instead of shifting
+// every chunk after it. Paired chunks are the same or changed, the rest added or removed
+pub fn diff_chunks<'a>(original: &'a [Chunk], other: &'a [Chunk]) -> Vec<ChunkDiff<'a>> {
+    // common[i][j] is the length of the longest common type sequence of original[i..] and other[j..]
+    let mut common = vec![vec![0usize; other.len() + 1]; original.len() + 1];
+    for i in (0..original.len()).rev() {
+        for j in (0..other.len()).rev() {
+            common[i][j] = if original[i].chunk_type() == other[j].chunk_type() {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < original.len() || j < other.len() {
+        if i < original.len() && j < other.len() && original[i].chunk_type() == other[j].chunk_type() {
+            diff.push(if original[i] == other[j] {
+                ChunkDiff::Same(&original[i])
+            } else {
+                ChunkDiff::Changed(&original[i], &other[j])
+            });
+            i += 1;
+            j += 1;
+        } else if j < other.len() && (i == original.len() || common[i][j + 1] >= common[i + 1][j]) {
+            diff.push(ChunkDiff::Added(&other[j]));
+            j += 1;
+        } else {
+            diff.push(ChunkDiff::Removed(&original[i]));
+            i += 1;
+        }
+    }
+    diff
+}
+
+impl fmt::Display for ChunkDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkDiff::Same(chunk) => write!(f, "same     {}", chunk.chunk_type()),
+            ChunkDiff::Changed(a, b) => write!(
+                f,
+                "changed  {} -> {} (crc {:08x} -> {:08x})",
+                a.chunk_type(),
+                b.chunk_type(),
+                a.crc(),
+                b.crc()
+            ),
+            ChunkDiff::Added(chunk) => write!(f, "added    {} (crc {:08x})", chunk.chunk_type(), chunk.crc()),
+            ChunkDiff::Removed(chunk) => write!(f, "removed  {} (crc {:08x})", chunk.chunk_type(), chunk.crc()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_diff_chunk_inserted_before_iend() {
+        let original = vec![chunk("IHDR", "header"), chunk("IDAT", "pixels"), chunk("IEND", "")];
+        let mut other = original.clone();
+        other.insert(2, chunk("ruSt", "hidden"));
+
+        let diff = diff_chunks(&original, &other);
+        assert_eq!(diff.len(), 4);
+        assert!(matches!(diff[0], ChunkDiff::Same(_)));
+        assert!(matches!(diff[1], ChunkDiff::Same(_)));
+        match diff[2] {
+            ChunkDiff::Added(chunk) => assert_eq!(&chunk.chunk_type().to_string(), "ruSt"),
+            _ => panic!("expected an added chunk"),
+        }
+        assert!(matches!(diff[3], ChunkDiff::Same(_)));
+    }
+
+    #[test]
+    fn test_diff_chunk_removed_from_middle() {
+        let original = vec![chunk("IHDR", "header"), chunk("ruSt", "hidden"), chunk("IEND", "")];
+        let other = vec![chunk("IHDR", "header"), chunk("IEND", "")];
+
+        let diff = diff_chunks(&original, &other);
+        assert_eq!(diff.len(), 3);
+        assert!(matches!(diff[1], ChunkDiff::Removed(_)));
+        assert!(matches!(diff[2], ChunkDiff::Same(_)));
+    }
+
+    #[test]
+    fn test_diff_changed_and_removed() {
+        let original = vec![chunk("IHDR", "header"), chunk("ruSt", "one"), chunk("IEND", "")];
+        let other = vec![chunk("IHDR", "header"), chunk("ruSt", "two")];
+
+        let diff = diff_chunks(&original, &other);
+        assert!(matches!(diff[1], ChunkDiff::Changed(_, _)));
+        assert!(matches!(diff[2], ChunkDiff::Removed(_)));
+    }
+}
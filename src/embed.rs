@@ -0,0 +1,77 @@
+use std::convert::TryInto;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::split;
+use crate::Result;
+
+// a whole file is stored as a split message whose first piece is a header with
+// the original file name and length, the remaining pieces hold the file contents
+const MAGIC: [u8; 4] = *b"FILE";
+
+pub fn embed_file(chunk_type: ChunkType, name: &str, contents: &[u8], size: usize) -> Result<Vec<Chunk>> {
+    if size == 0 {
+        return Err("Chunk size must be greater than zero".into());
+    }
+    let name_length: u16 = name.len().try_into().map_err(|_| "File name is too long")?;
+
+    let header: Vec<u8> = MAGIC
+        .iter()
+        .copied()
+        .chain(name_length.to_be_bytes().iter().copied())
+        .chain(name.bytes())
+        .chain((contents.len() as u64).to_be_bytes().iter().copied())
+        .collect();
+    let pieces: Vec<&[u8]> = std::iter::once(header.as_slice())
+        .chain(contents.chunks(size))
+        .collect();
+
+    split::split_pieces(chunk_type, &pieces)
+}
+
+// the original file name and contents of a file stored by 'embed_file'
+pub fn extract_file(chunks: &[&Chunk]) -> Result<(String, Vec<u8>)> {
+    let payload = split::join_chunks(chunks)?.ok_or("Chunks do not hold an embedded file")?;
+    if payload.len() < 6 || payload[..4] != MAGIC {
+        return Err("Chunks do not hold an embedded file".into());
+    }
+
+    let name_length = u16::from_be_bytes([payload[4], payload[5]]) as usize;
+    let name_end = 6 + name_length;
+    if payload.len() < name_end + 8 {
+        return Err("Embedded file header is truncated".into());
+    }
+    let name = String::from_utf8(payload[6..name_end].to_vec())?;
+    let length = u64::from_be_bytes(payload[name_end..name_end + 8].try_into()?);
+
+    let contents = payload[name_end + 8..].to_vec();
+    if contents.len() as u64 != length {
+        return Err(format!("Embedded file should be {} bytes, found {}", length, contents.len()).into());
+    }
+
+    Ok((name, contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_embed_and_extract_file() {
+        let contents: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        let chunks = embed_file(ChunkType::from_str("fiLe").unwrap(), "notes.txt", &contents, 1024).unwrap();
+        // header plus five pieces of file contents
+        assert_eq!(chunks.len(), 6);
+
+        let (name, extracted) = extract_file(&chunks.iter().collect::<Vec<_>>()).unwrap();
+        assert_eq!(name, "notes.txt");
+        assert_eq!(extracted, contents);
+    }
+
+    #[test]
+    fn test_extract_plain_split_message() {
+        let chunks = split::split_message(ChunkType::from_str("fiLe").unwrap(), b"not a file", 4).unwrap();
+        assert!(extract_file(&chunks.iter().collect::<Vec<_>>()).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+use std::error;
+use std::fmt;
+
+/// Errors for input that isn't a well-formed PNG, or lacks a requested chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PngError {
+    /// The input doesn't start with the PNG signature.
+    InvalidSignature,
+    /// A chunk's stored CRC doesn't match its type and data.
+    InvalidChunk,
+    /// A chunk's length field claims more bytes than the input has left.
+    TruncatedChunk,
+    /// No chunk of the requested type exists.
+    ChunkNotFound,
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PngError::InvalidSignature => write!(f, "Header doesnt match PNG signature header"),
+            PngError::InvalidChunk => write!(f, "Invalid chunk"),
+            PngError::TruncatedChunk => write!(f, "Chunk length exceeds the remaining input"),
+            PngError::ChunkNotFound => write!(f, "Could not find chunk"),
+        }
+    }
+}
+
+impl error::Error for PngError {}
@@ -0,0 +1,152 @@
+use std::convert::TryFrom;
+
+use crate::chunk::Chunk;
+use crate::{Error, Result};
+
+// IHDR is the first chunk of every PNG and describes the image
+// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.IHDR
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub compression: u8,
+    pub filter: u8,
+    pub interlace: u8,
+}
+
+impl Ihdr {
+    const LENGTH: usize = 13;
+
+    // number of samples per pixel for the color type
+    pub fn channels(&self) -> u8 {
+        match self.color_type {
+            0 => 1, // greyscale
+            2 => 3, // RGB
+            3 => 1, // palette index
+            4 => 2, // greyscale and alpha
+            6 => 4, // RGB and alpha
+            _ => 0,
+        }
+    }
+
+    pub fn color_type_name(&self) -> &'static str {
+        match self.color_type {
+            0 => "greyscale",
+            2 => "RGB",
+            3 => "indexed",
+            4 => "greyscale and alpha",
+            6 => "RGB and alpha",
+            _ => "unknown",
+        }
+    }
+
+    // bytes that can be hidden in the least significant 'bits' of every sample
+    // indexed images store palette indices, flipping their low bits swaps in an
+    // unrelated palette entry instead of nudging the color, so they are refused
+    // 16-bit samples are two bytes wide but only the low byte is used, so they
+    // hold as much as 8-bit ones
+    pub fn lsb_capacity(&self, bits: u8) -> Result<u64> {
+        if self.color_type == 3 {
+            return Err("LSB embedding is not supported for indexed (palette) images".into());
+        }
+        if self.bit_depth != 8 && self.bit_depth != 16 {
+            return Err(format!("LSB embedding needs a bit depth of 8 or 16, found {}", self.bit_depth).into());
+        }
+        Ok(self.width as u64 * self.height as u64 * self.channels() as u64 * bits as u64 / 8)
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().to_string() != "IHDR" {
+            return Err("Not an IHDR chunk".into());
+        }
+        let data = chunk.data();
+        if data.len() != Ihdr::LENGTH {
+            return Err(format!("IHDR must be {} bytes long, found {}", Ihdr::LENGTH, data.len()).into());
+        }
+
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            bit_depth: data[8],
+            color_type: data[9],
+            compression: data[10],
+            filter: data[11],
+            interlace: data[12],
+        };
+        if ihdr.channels() == 0 {
+            return Err(format!("Unknown color type {}", ihdr.color_type).into());
+        }
+
+        Ok(ihdr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn ihdr_chunk(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Chunk {
+        let data = [
+            &width.to_be_bytes()[..],
+            &height.to_be_bytes()[..],
+            &[bit_depth, color_type, 0, 0, 0],
+        ]
+        .concat();
+        Chunk::new(ChunkType::from_str("IHDR").unwrap(), data)
+    }
+
+    #[test]
+    fn test_parse_ihdr() {
+        let ihdr = Ihdr::try_from(&ihdr_chunk(50, 40, 8, 6)).unwrap();
+        assert_eq!(ihdr.width, 50);
+        assert_eq!(ihdr.height, 40);
+        assert_eq!(ihdr.bit_depth, 8);
+        assert_eq!(ihdr.channels(), 4);
+        assert_eq!(ihdr.color_type_name(), "RGB and alpha");
+    }
+
+    #[test]
+    fn test_lsb_capacity() {
+        let ihdr = Ihdr::try_from(&ihdr_chunk(50, 40, 8, 2)).unwrap();
+        assert_eq!(ihdr.lsb_capacity(1).unwrap(), 50 * 40 * 3 / 8);
+        assert_eq!(ihdr.lsb_capacity(2).unwrap(), 50 * 40 * 3 * 2 / 8);
+    }
+
+    #[test]
+    fn test_lsb_capacity_16_bit() {
+        let ihdr = Ihdr::try_from(&ihdr_chunk(50, 40, 16, 6)).unwrap();
+        assert_eq!(ihdr.lsb_capacity(1).unwrap(), 50 * 40 * 4 / 8);
+        assert_eq!(ihdr.lsb_capacity(4).unwrap(), 50 * 40 * 4 * 4 / 8);
+    }
+
+    #[test]
+    fn test_lsb_capacity_unsupported_depth() {
+        let ihdr = Ihdr::try_from(&ihdr_chunk(50, 40, 4, 0)).unwrap();
+        let err = ihdr.lsb_capacity(1).unwrap_err();
+        assert!(err.to_string().contains("bit depth"));
+    }
+
+    #[test]
+    fn test_lsb_capacity_indexed() {
+        let ihdr = Ihdr::try_from(&ihdr_chunk(50, 40, 8, 3)).unwrap();
+        let err = ihdr.lsb_capacity(1).unwrap_err();
+        assert!(err.to_string().contains("indexed"));
+    }
+
+    #[test]
+    fn test_invalid_ihdr() {
+        assert!(Ihdr::try_from(&ihdr_chunk(1, 1, 8, 5)).is_err());
+        let short = Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 12]);
+        assert!(Ihdr::try_from(&short).is_err());
+        let other = Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![0; 13]);
+        assert!(Ihdr::try_from(&other).is_err());
+    }
+}
@@ -0,0 +1,14 @@
+pub mod chunk;
+pub mod chunk_type;
+pub mod diff;
+pub mod embed;
+pub mod error;
+pub mod ihdr;
+pub mod png;
+pub mod render;
+pub mod split;
+pub mod text;
+pub mod validate;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
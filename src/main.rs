@@ -1,46 +1,122 @@
 mod args;
-mod chunk;
-mod chunk_type;
+mod color;
 mod commands;
-mod png;
 
-use std::{env, path::PathBuf, str::FromStr};
+use std::io;
+use std::process;
 
 use clap::Clap;
 use commands::{CmdOptions, Commands};
+use png_message::error::PngError;
+use png_message::{Error, Result};
 
-use crate::{chunk_type::ChunkType};
-
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+// process exit codes, so scripts can branch on the kind of failure.
+// 2 is left to clap, which exits with it when the arguments can't be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitCode {
+    Generic = 1,
+    NotFound = 3,
+    BadPng = 4,
+}
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+fn exit_code(err: &Error) -> ExitCode {
+    if let Some(err) = err.downcast_ref::<PngError>() {
+        match err {
+            PngError::ChunkNotFound => ExitCode::NotFound,
+            PngError::InvalidSignature | PngError::InvalidChunk | PngError::TruncatedChunk => ExitCode::BadPng,
+        }
+    } else if let Some(err) = err.downcast_ref::<io::Error>() {
+        match err.kind() {
+            io::ErrorKind::NotFound => ExitCode::NotFound,
+            // a chunk claims more bytes than the file has left
+            io::ErrorKind::UnexpectedEof => ExitCode::BadPng,
+            _ => ExitCode::Generic,
+        }
+    } else {
+        ExitCode::Generic
+    }
+}
 
+fn main() {
     let cmd_options: CmdOptions = CmdOptions::parse();
 
-    match cmd_options.sub_command {
-        Commands::Encode(mut e) => {
-            e.file_path = PathBuf::from(args.get(2).expect("Invalid index"));
-            e.chunk_type = ChunkType::from_str(args.get(3).expect("invalid index")).expect("invalid chunk type");
-            e.message = args.get(4).expect("invalid index").to_string();
-            args::encode(e)?
-        }
-        Commands::Decode(mut d) => {
-            d.file_path = PathBuf::from(args.get(2).expect("Invalid index"));
-            d.chunk_type = ChunkType::from_str(args.get(3).expect("invalid index")).expect("invalid chunk type");
-            args::decode(d)?
+    // RUST_LOG overrides the level picked by --verbose
+    env_logger::Builder::new()
+        .filter_level(args::log_level(cmd_options.verbose))
+        .parse_default_env()
+        .format_timestamp(None)
+        .init();
+
+    let progress = args::Progress::new(cmd_options.verbose, io::stderr());
+    if let Err(err) = run(cmd_options.sub_command, &progress) {
+        eprintln!("Error: {}", err);
+        process::exit(exit_code(&err) as i32);
+    }
+}
+
+fn run(sub_command: Commands, progress: &args::Progress) -> Result<()> {
+    match sub_command {
+        Commands::Encode(e) => args::encode(e, progress)?,
+        Commands::Decode(d) => args::decode(d, &mut io::stdout())?,
+        Commands::Remove(r) => args::remove(r, &mut io::stdout())?,
+        Commands::Print(p) => args::print(p, &mut io::stdout(), progress)?,
+        Commands::List(l) => args::list(l, &mut io::stdout())?,
+        Commands::Search(s) => {
+            let count_only = s.count_only;
+            if args::search(s, &mut io::stdout())? == 0 && count_only {
+                process::exit(ExitCode::Generic as i32);
+            }
         }
-        Commands::Remove(mut r) => {
-            r.file_path = PathBuf::from(args.get(2).expect("Invalid index"));
-            r.chunk_type = ChunkType::from_str(args.get(3).expect("invalid index")).expect("invalid chunk type");
-            args::remove(r)?
+        Commands::CopyChunk(c) => args::copy_chunk(c)?,
+        Commands::Merge(m) => args::merge(m)?,
+        Commands::Diff(d) => args::diff(d, &mut io::stdout())?,
+        Commands::Capacity(c) => args::capacity(c)?,
+        Commands::Info(i) => args::info(i, &mut io::stdout())?,
+        Commands::Dedupe(d) => args::dedupe(d)?,
+        Commands::Canonicalize(c) => args::canonicalize(c)?,
+        Commands::Strip(s) => args::strip(s, &mut io::stdout(), progress)?,
+        Commands::Verify(v) => args::verify(v, &mut io::stdout())?,
+        Commands::Validate(v) => args::validate(v, &mut io::stdout())?,
+        Commands::RenameChunk(r) => args::rename_chunk(r)?,
+        Commands::Swap(s) => args::swap(s)?,
+        Commands::ScanTrailing(s) => args::scan_trailing(s, &mut io::stdout())?,
+        Commands::EmbedFile(e) => args::embed_file(e)?,
+        Commands::ExtractFile(e) => args::extract_file(e)?,
+        Commands::Convert(c) => args::convert(c)?,
+        Commands::Hash(h) => args::hash(h, &mut io::stdout())?,
+        Commands::Undo(u) => args::undo(u)?,
+        Commands::Repl(r) => args::repl(r, io::stdin().lock(), &mut io::stdout())?,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    use commands::Decode;
+
+    fn decode_error(name: &str, contents: Option<&[u8]>) -> Error {
+        let path = env::temp_dir().join(format!("png_message_{}_{}.png", process::id(), name));
+        if let Some(contents) = contents {
+            fs::write(&path, contents).unwrap();
         }
-        Commands::Print(mut p) => {
-            p.file_path = PathBuf::from(args.get(2).expect("Invalid index"));
-            args::print(p)?
+        let result = run(Commands::Decode(Decode::parse_from(["decode", path.to_str().unwrap(), "ruSt"])), &args::Progress::quiet());
+        if contents.is_some() {
+            fs::remove_file(&path).unwrap();
         }
+        result.unwrap_err()
     }
 
-    Ok(())
+    #[test]
+    fn test_exit_code_missing_file() {
+        assert_eq!(exit_code(&decode_error("exit_missing", None)), ExitCode::NotFound);
+    }
+
+    #[test]
+    fn test_exit_code_malformed_png() {
+        assert_eq!(exit_code(&decode_error("exit_malformed", Some(b"not a png file"))), ExitCode::BadPng);
+    }
 }
@@ -1,13 +1,21 @@
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::fmt;
-use std::usize;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
 
-use crate::chunk::Chunk;
+use log::debug;
+
+use crate::chunk::{Chunk, ChunkParseOptions};
+use crate::chunk_type::ChunkType;
+use crate::error::PngError;
 use crate::{Error, Result};
 
 #[derive(Debug)]
 pub struct Png {
     chunks: Vec<Chunk>,
+    trailing: Vec<u8>,
 }
 
 impl Png {
@@ -17,14 +25,50 @@ impl Png {
     const HEADER_INDEX_END: usize = 8;
 
     fn from_chunks(chunks: Vec<Chunk>) -> Self {
-        Self { chunks }
+        Self {
+            chunks,
+            trailing: Vec::new(),
+        }
     }
 
+    /// Build a PNG from `chunks`, failing unless IHDR is the first one as readers require.
+    /// `PngBuilder` skips the check.
+    pub fn try_from_chunks(chunks: Vec<Chunk>) -> Result<Self> {
+        check_ihdr_first(&chunks)?;
+        Ok(Png::from_chunks(chunks))
+    }
+
+    // add 'chunk' as the last chunk before IEND, readers stop at IEND so anything after it
+    // is trailing data. Without an IEND it goes at the very end
     pub fn append_chunk(&mut self, chunk: Chunk) {
-        self.chunks.push(chunk);
+        self.insert_before_end(chunk);
+    }
+
+    /// Wrap the PNG so chunks can only be appended to it.
+    pub fn append_only(self) -> AppendOnlyPng {
+        AppendOnlyPng { png: self }
+    }
+
+    // insert 'chunk' so it ends up at 'index' in file order
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+        if index > self.chunks.len() {
+            return Err(format!("Chunk index out of range, the file has {} chunks", self.chunks.len()).into());
+        }
+        self.chunks.insert(index, chunk);
+        Ok(())
     }
 
-    // remove first occurence of 'chunk_type'
+    // insert 'chunk' right before IEND, or at the end if there is no IEND
+    pub fn insert_before_end(&mut self, chunk: Chunk) {
+        let index = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == "IEND")
+            .unwrap_or(self.chunks.len());
+        self.chunks.insert(index, chunk);
+    }
+
+    // remove the first occurence of 'chunk_type' and return it
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
         if let Some(index) = self
             .chunks
@@ -33,10 +77,65 @@ impl Png {
         {
             Ok(self.chunks.remove(index))
         } else {
-            Err("Could not find chunk".into())
+            Err(PngError::ChunkNotFound.into())
         }
     }
 
+    // remove chunks with the same type and data as an earlier chunk, returns how many were removed
+    pub fn dedupe(&mut self) -> usize {
+        let mut seen = HashSet::new();
+        let keep: Vec<bool> = self
+            .chunks
+            .iter()
+            .map(|chunk| seen.insert(chunk))
+            .collect();
+
+        let before = self.chunks.len();
+        let mut keep = keep.into_iter();
+        self.chunks.retain(|_| keep.next().unwrap_or(true));
+        before - self.chunks.len()
+    }
+
+    // reorder the chunks into the order the spec lays out, returns how many changed position.
+    // Only chunks the spec places are moved: a chunk that may go anywhere stays on the same
+    // side of IDAT, and ties keep their original order so IDAT chunks stay in sequence
+    pub fn canonicalize(&mut self) -> usize {
+        let first_idat = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == "IDAT")
+            .unwrap_or(self.chunks.len());
+        let mut ranked: Vec<(usize, Chunk)> = self.chunks.drain(..).enumerate().collect();
+        ranked.sort_by_key(|(index, chunk)| (canonical_group(chunk, *index < first_idat), *index));
+        let moved = ranked
+            .iter()
+            .enumerate()
+            .filter(|(position, (index, _))| position != index)
+            .count();
+        self.chunks = ranked.into_iter().map(|(_, chunk)| chunk).collect();
+        moved
+    }
+
+    // remove every ancillary chunk whose type isn't in 'keep', returns the removed chunks
+    pub fn strip(&mut self, keep: &[ChunkType]) -> Vec<Chunk> {
+        let (kept, removed) = self
+            .chunks
+            .drain(..)
+            .partition(|chunk| chunk.chunk_type().is_critical() || keep.contains(chunk.chunk_type()));
+        self.chunks = kept;
+        removed
+    }
+
+    // exchange the chunks at 'a' and 'b' in file order
+    pub fn swap_chunks(&mut self, a: usize, b: usize) -> Result<()> {
+        let count = self.chunks.len();
+        if a >= count || b >= count {
+            return Err(format!("Chunk index out of range, the file has {} chunks", count).into());
+        }
+        self.chunks.swap(a, b);
+        Ok(())
+    }
+
     fn header(&self) -> &[u8; 8] {
         &Png::STANDARD_HEADER
     }
@@ -45,17 +144,79 @@ impl Png {
         &self.chunks
     }
 
-    // get first occurence of 'chunk_type'
+    pub fn iter(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.iter()
+    }
+
+    // byte offset of every chunk from the start of the file, the first one follows the signature
+    pub fn chunk_offsets(&self) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .scan(Png::HEADER_INDEX_END, |offset, chunk| {
+                let start = *offset;
+                *offset += chunk.total_size();
+                Some(start)
+            })
+            .collect()
+    }
+
+    /// The first chunk of type `chunk_type` in file order, or `None` if there is no such chunk.
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        if let Some(typee) = self
-            .chunks
+        self.chunks
             .iter()
-            .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
-        {
-            self.chunks.get(typee)
-        } else {
-            None
-        }
+            .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Mutable access to the first chunk of type `chunk_type` in file order.
+    pub fn chunk_by_type_mut(&mut self, chunk_type: &str) -> Option<&mut Chunk> {
+        self.chunks
+            .iter_mut()
+            .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    // get every occurence of 'chunk_type' in file order
+    pub fn chunks_by_type(&self, chunk_type: &str) -> Vec<&Chunk> {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == chunk_type)
+            .collect()
+    }
+
+    // find every chunk whose data contains 'needle', with the position of the first match
+    pub fn search(&self, needle: &[u8]) -> Vec<(&Chunk, usize)> {
+        self.chunks
+            .iter()
+            .filter_map(|chunk| {
+                if needle.is_empty() || needle.len() > chunk.data().len() {
+                    return None;
+                }
+                chunk
+                    .data()
+                    .windows(needle.len())
+                    .position(|window| window == needle)
+                    .map(|position| (chunk, position))
+            })
+            .collect()
+    }
+
+    // number of chunks, IHDR and IEND included
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    // true for a PNG that is only the signature
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Bytes after IEND that don't form chunks, kept so writing the file back preserves them.
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing
+    }
+
+    // size of the serialized file: the signature, every chunk and any trailing data
+    pub fn total_size(&self) -> usize {
+        self.header().len() + self.chunks.iter().map(Chunk::total_size).sum::<usize>() + self.trailing.len()
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
@@ -65,31 +226,284 @@ impl Png {
                 .iter()
                 .flat_map(|x| x.as_bytes().to_vec())
                 .collect(),
+            self.trailing.clone(),
         ]
         .concat()
     }
+
+    /// Write the file as `as_bytes` would, streaming each chunk to `w` instead of
+    /// collecting the whole file in memory first.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(self.header())?;
+        for chunk in &self.chunks {
+            chunk.write_to(w)?;
+        }
+        w.write_all(&self.trailing)
+    }
+
+    /// Like `as_bytes`, but fails instead of serializing a file without IHDR first.
+    pub fn as_bytes_checked(&self) -> Result<Vec<u8>> {
+        check_ihdr_first(&self.chunks)?;
+        Ok(self.as_bytes())
+    }
 }
 
-impl TryFrom<&[u8]> for Png {
-    type Error = Error;
+// group of a chunk in the canonical layout, from the ordering constraints in
+// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.Summary-of-standard-chunks
+// and the registered extensions. 'before_idat' is where the chunk was found, which decides
+// the group of chunks the spec lets go anywhere
+fn canonical_group(chunk: &Chunk, before_idat: bool) -> u8 {
+    match &chunk.chunk_type().to_string()[..] {
+        "IHDR" => 0,
+        // before PLTE and IDAT
+        "cHRM" | "gAMA" | "iCCP" | "sBIT" | "sRGB" | "cICP" | "mDCV" | "cLLI" => 1,
+        "PLTE" => 2,
+        // after PLTE and before IDAT
+        "bKGD" | "hIST" | "tRNS" => 3,
+        // before IDAT, either side of PLTE
+        "pHYs" | "sPLT" | "sCAL" | "oFFs" | "pCAL" | "eXIf" | "sTER" | "acTL" => 3,
+        "IDAT" => 4,
+        "IEND" => 6,
+        // anything else, including unknown chunks, stays on its side of IDAT
+        _ if before_idat => 3,
+        _ => 5,
+    }
+}
 
-    fn try_from(bytes: &[u8]) -> Result<Self> {
-        if bytes[Png::HEADER_INDEX_START..Png::HEADER_INDEX_END] != Png::STANDARD_HEADER {
-            return Err("Header doesnt match PNG signature header".into());
+/// Options for parsing a whole PNG file.
+#[derive(Debug, Clone, Copy)]
+pub struct PngParseOptions {
+    /// Fail on a chunk whose stored CRC doesn't match, see `ChunkParseOptions`.
+    pub verify_crc: bool,
+    /// Keep reading chunks after IEND, as written by older versions that appended
+    /// messages to the end of the file. Bytes that don't form a chunk are still
+    /// trailing data.
+    pub chunks_after_iend: bool,
+}
+
+impl Default for PngParseOptions {
+    fn default() -> Self {
+        PngParseOptions {
+            verify_crc: true,
+            chunks_after_iend: false,
+        }
+    }
+}
+
+fn check_ihdr_first(chunks: &[Chunk]) -> Result<()> {
+    match chunks.iter().position(|chunk| chunk.chunk_type().to_string() == "IHDR") {
+        Some(0) => Ok(()),
+        Some(index) => Err(format!("IHDR must be the first chunk, found it at index {}", index).into()),
+        None => Err("IHDR must be the first chunk, found none".into()),
+    }
+}
+
+impl Png {
+    // read and parse the PNG file at 'path'
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        // keep the io::ErrorKind so callers can still tell a missing file apart
+        let bytes = fs::read(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("Could not read {}: {}", path.display(), err)))?;
+        Png::try_from(bytes.as_slice())
+    }
+
+    // parse like 'try_from' but call 'progress' with (bytes parsed, total bytes) after every chunk
+    pub fn try_from_with_progress<F>(bytes: &[u8], mut progress: F) -> Result<Self>
+    where
+        F: FnMut(usize, usize),
+    {
+        Png::parse(bytes, PngParseOptions::default(), &mut progress).map(|(png, _)| png)
+    }
+
+    // parse, keeping chunks with a bad CRC and returning a warning for each of them
+    pub fn try_from_lenient(bytes: &[u8]) -> Result<(Self, Vec<String>)> {
+        Png::try_from_opts(
+            bytes,
+            PngParseOptions {
+                verify_crc: false,
+                ..PngParseOptions::default()
+            },
+        )
+    }
+
+    // parse with 'options', returning a warning for each chunk kept with a bad CRC
+    pub fn try_from_opts(bytes: &[u8], options: PngParseOptions) -> Result<(Self, Vec<String>)> {
+        Png::parse(bytes, options, &mut |_, _| {})
+    }
+
+    // 'try_from_opts' calling 'progress' like 'try_from_with_progress' does
+    pub fn try_from_opts_with_progress<F>(
+        bytes: &[u8],
+        options: PngParseOptions,
+        mut progress: F,
+    ) -> Result<(Self, Vec<String>)>
+    where
+        F: FnMut(usize, usize),
+    {
+        Png::parse(bytes, options, &mut progress)
+    }
+
+    fn parse(
+        bytes: &[u8],
+        options: PngParseOptions,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<(Self, Vec<String>)> {
+        if bytes.get(Png::HEADER_INDEX_START..Png::HEADER_INDEX_END) != Some(&Png::STANDARD_HEADER[..]) {
+            return Err(PngError::InvalidSignature.into());
         }
 
         let mut data_chunks: Vec<Chunk> = Vec::new();
+        let mut warnings = Vec::new();
         let mut bytes_cursor = 8;
+        let mut after_iend = false;
 
         while bytes_cursor < bytes.len() {
-            let chunk = Chunk::try_from(&bytes[bytes_cursor..])?;
-            bytes_cursor += chunk.as_bytes().len();
+            // everything after IEND is trailing data unless chunks there were asked for
+            if after_iend && !options.chunks_after_iend {
+                break;
+            }
+            let chunk_options = ChunkParseOptions {
+                verify_crc: options.verify_crc,
+            };
+            let parsed = Chunk::try_from_opts(&bytes[bytes_cursor..], chunk_options);
+            let chunk = match parsed {
+                Ok(chunk) => chunk,
+                Err(_) if after_iend => break,
+                Err(err) => return Err(err),
+            };
+            after_iend |= chunk.chunk_type().to_string() == "IEND";
+            debug!("Parsed {} chunk at offset {}", chunk.chunk_type(), bytes_cursor);
+            if !chunk.has_valid_crc() {
+                warnings.push(format!(
+                    "CRC mismatch in {} chunk at offset {}",
+                    chunk.chunk_type(),
+                    bytes_cursor
+                ));
+            }
+            bytes_cursor += chunk.total_size();
             data_chunks.push(chunk);
+            progress(bytes_cursor, bytes.len());
+        }
+
+        let mut png = Png::from_chunks(data_chunks);
+        png.trailing = bytes[bytes_cursor..].to_vec();
+
+        Ok((png, warnings))
+    }
+}
+
+/// Parse every chunk of a PNG file with its byte offset, keeping going past chunks with a
+/// bad CRC. A chunk whose length or type can't be read ends the list, since the next one
+/// can't be found. A bad signature gives a single error at offset 0.
+pub fn parse_chunks(bytes: &[u8]) -> Vec<(usize, Result<Chunk>)> {
+    if bytes.get(Png::HEADER_INDEX_START..Png::HEADER_INDEX_END) != Some(&Png::STANDARD_HEADER[..]) {
+        return vec![(0, Err(PngError::InvalidSignature.into()))];
+    }
+
+    let mut parsed = Vec::new();
+    let mut offset = Png::HEADER_INDEX_END;
+    while offset < bytes.len() {
+        match Chunk::try_from_opts(&bytes[offset..], ChunkParseOptions { verify_crc: false }) {
+            Ok(chunk) => {
+                let size = chunk.total_size();
+                let result = if chunk.has_valid_crc() {
+                    Ok(chunk)
+                } else {
+                    Err(PngError::InvalidChunk.into())
+                };
+                parsed.push((offset, result));
+                offset += size;
+            }
+            Err(err) => {
+                parsed.push((offset, Err(err)));
+                break;
+            }
+        }
+    }
+    parsed
+}
+
+/// A PNG that can only grow: chunks can be appended but existing ones can't be removed,
+/// moved or changed. Read access goes through `Deref` to `Png`.
+#[derive(Debug)]
+pub struct AppendOnlyPng {
+    png: Png,
+}
+
+impl AppendOnlyPng {
+    /// Add `chunk` after every existing chunk but before IEND, where a reader still sees it.
+    pub fn append_chunk(&mut self, chunk: Chunk) {
+        self.png.insert_before_end(chunk);
+    }
+
+    /// Always fails, existing chunks can't be removed.
+    pub fn remove_chunk(&mut self, _chunk_type: &str) -> Result<Chunk> {
+        Err("Cannot remove chunks from an append-only PNG".into())
+    }
+
+    /// Always fails, chunks can only be added at the end.
+    pub fn insert_chunk(&mut self, _index: usize, _chunk: Chunk) -> Result<()> {
+        Err("Cannot insert chunks into an append-only PNG, only append them".into())
+    }
+
+    pub fn into_inner(self) -> Png {
+        self.png
+    }
+}
+
+impl std::ops::Deref for AppendOnlyPng {
+    type Target = Png;
+
+    fn deref(&self) -> &Png {
+        &self.png
+    }
+}
+
+/// Assembles a PNG one chunk at a time without reserializing the chunks added so far.
+#[derive(Debug, Default)]
+pub struct PngBuilder {
+    chunks: Vec<Chunk>,
+}
+
+impl PngBuilder {
+    pub fn new() -> Self {
+        PngBuilder::default()
+    }
+
+    pub fn push_chunk(&mut self, chunk: Chunk) -> &mut Self {
+        self.chunks.push(chunk);
+        self
+    }
+
+    pub fn build(self) -> Png {
+        Png::from_chunks(self.chunks)
+    }
+
+    /// Write the signature and the chunks pushed so far.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&Png::STANDARD_HEADER)?;
+        for chunk in &self.chunks {
+            chunk.write_to(w)?;
         }
+        Ok(())
+    }
+}
 
-        let png = Png::from_chunks(data_chunks);
+impl TryFrom<&[u8]> for Png {
+    type Error = Error;
 
-        Ok(png)
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        Png::try_from_with_progress(bytes, |_, _| {})
+    }
+}
+
+impl<'a> IntoIterator for &'a Png {
+    type Item = &'a Chunk;
+    type IntoIter = std::slice::Iter<'a, Chunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.iter()
     }
 }
 
@@ -99,7 +513,7 @@ impl fmt::Display for Png {
             f,
             "Header:{:?}\nChunks: {}\n",
             self.header(),
-            self.chunks.len()
+            self.chunk_count()
         )
     }
 }
@@ -107,19 +521,19 @@ impl fmt::Display for Png {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chunk::arb_chunk;
+    use proptest::prelude::*;
     use crate::chunk::Chunk;
     use crate::chunk_type::ChunkType;
     use std::convert::TryFrom;
     use std::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -142,6 +556,45 @@ mod tests {
         assert_eq!(png.chunks().len(), 3);
     }
 
+    #[test]
+    fn test_try_from_chunks_needs_ihdr_first() {
+        let ihdr = chunk_from_strings("IHDR", "header").unwrap();
+        let missing = Png::try_from_chunks(testing_chunks()).unwrap_err();
+        assert!(missing.to_string().contains("found none"));
+
+        let mut misplaced = testing_chunks();
+        misplaced.insert(1, ihdr.clone());
+        assert!(Png::try_from_chunks(misplaced).unwrap_err().to_string().contains("index 1"));
+
+        let mut chunks = testing_chunks();
+        chunks.insert(0, ihdr);
+        assert_eq!(Png::try_from_chunks(chunks).unwrap().chunk_count(), 4);
+    }
+
+    #[test]
+    fn test_write_to_matches_as_bytes() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut streamed = Vec::new();
+        png.write_to(&mut streamed).unwrap();
+        assert_eq!(streamed, png.as_bytes());
+
+        let mut trailing = PNG_FILE.to_vec();
+        trailing.extend_from_slice(b"trailing");
+        let png = Png::try_from(trailing.as_slice()).unwrap();
+        let mut streamed = Vec::new();
+        png.write_to(&mut streamed).unwrap();
+        assert_eq!(streamed, trailing);
+    }
+
+    #[test]
+    fn test_as_bytes_checked() {
+        assert!(testing_png().as_bytes_checked().is_err());
+
+        let mut png = testing_png();
+        png.insert_chunk(0, chunk_from_strings("IHDR", "header").unwrap()).unwrap();
+        assert_eq!(png.as_bytes_checked().unwrap(), png.as_bytes());
+    }
+
     #[test]
     fn test_valid_from_bytes() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -200,6 +653,25 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_lenient_parse_bad_crc() {
+        let mut bytes = PNG_FILE.to_vec();
+        // corrupt the CRC of the sRGB chunk at offset 33
+        bytes[33 + 4 + 4 + 1] ^= 0xff;
+        let mut chunk_bytes = chunk_from_strings("ruSt", "hidden message").unwrap().as_bytes();
+        let iend = bytes.len() - 12;
+        bytes.splice(iend..iend, chunk_bytes.drain(..));
+
+        assert!(Png::try_from(bytes.as_ref()).is_err());
+        let (png, warnings) = Png::try_from_lenient(bytes.as_ref()).unwrap();
+        assert_eq!(warnings, ["CRC mismatch in sRGB chunk at offset 33"]);
+        assert_eq!(
+            &png.chunk_by_type("ruSt").unwrap().data_as_string().unwrap(),
+            "hidden message"
+        );
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -207,6 +679,65 @@ mod tests {
         assert_eq!(chunks.len(), 3);
     }
 
+    #[test]
+    fn test_iter_chunks() {
+        let png = testing_png();
+        let types: Vec<String> = png.iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["FrSt", "miDl", "LASt"]);
+
+        let mut looped = Vec::new();
+        for chunk in &png {
+            looped.push(chunk.chunk_type().to_string());
+        }
+        assert_eq!(looped, types);
+    }
+
+    #[test]
+    fn test_chunk_offsets() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let offsets = png.chunk_offsets();
+
+        // IHDR (13 bytes), sRGB (1 byte), gAMA (4 bytes), pHYs (9 bytes)
+        assert_eq!(&offsets[..5], [8, 33, 46, 62, 83]);
+        assert_eq!(&PNG_FILE[offsets[1] + 4..offsets[1] + 8], b"sRGB");
+    }
+
+    #[test]
+    fn test_parse_chunks_offsets() {
+        let parsed = parse_chunks(&PNG_FILE[..]);
+        let offsets: Vec<usize> = parsed.iter().map(|(offset, _)| *offset).collect();
+
+        assert_eq!(offsets, Png::try_from(&PNG_FILE[..]).unwrap().chunk_offsets());
+        assert!(parsed.iter().all(|(_, chunk)| chunk.is_ok()));
+    }
+
+    #[test]
+    fn test_parse_chunks_recovers_after_bad_crc() {
+        let mut bytes = PNG_FILE.to_vec();
+        // the sRGB rendering intent byte
+        bytes[33 + 8] ^= 0xff;
+        let parsed = parse_chunks(&bytes);
+
+        assert_eq!(parsed[1].0, 33);
+        assert!(parsed[1].1.is_err());
+        assert_eq!(parsed[0].1.as_ref().unwrap().chunk_type().to_string(), "IHDR");
+        assert_eq!(parsed[2].0, 46);
+        assert_eq!(parsed[2].1.as_ref().unwrap().chunk_type().to_string(), "gAMA");
+        assert!(parsed[3..].iter().all(|(_, chunk)| chunk.is_ok()));
+    }
+
+    #[test]
+    fn test_parse_chunks_stops_at_truncated_chunk() {
+        let mut bytes = PNG_FILE[..50].to_vec();
+        bytes.extend_from_slice(&[0, 0, 0]);
+        let parsed = parse_chunks(&bytes);
+
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[2].0, 46);
+        assert!(parsed[2].1.is_err());
+        assert!(parse_chunks(b"not a png")[0].1.is_err());
+    }
+
     #[test]
     fn test_chunk_by_type() {
         let png = testing_png();
@@ -215,6 +746,31 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
 
+    #[test]
+    fn test_chunk_by_type_first_match() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "I am a second middle chunk").unwrap());
+        let chunk = png.chunk_by_type("miDl").unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "I am another chunk");
+    }
+
+    #[test]
+    fn test_chunk_by_type_mut() {
+        let mut png = testing_png();
+        png.chunk_by_type_mut("miDl").unwrap().set_data(b"changed".to_vec());
+        assert_eq!(&png.chunk_by_type("miDl").unwrap().data_as_string().unwrap(), "changed");
+        assert!(png.chunk_by_type_mut("ruSt").is_none());
+    }
+
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "I am a second middle chunk").unwrap());
+        let chunks = png.chunks_by_type("miDl");
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(&chunks[1].data_as_string().unwrap(), "I am a second middle chunk");
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -224,6 +780,29 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_append_chunk_survives_reparse() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+
+        let reparsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(reparsed.chunk_by_type("ruSt").unwrap().data(), b"Message");
+        assert!(reparsed.trailing_data().is_empty());
+    }
+
+    #[test]
+    fn test_insert_before_end() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_before_end(chunk_from_strings("ruSt", "Message").unwrap());
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(&types[types.len() - 2..], ["ruSt", "IEND"]);
+
+        let mut png = testing_png();
+        png.insert_before_end(chunk_from_strings("ruSt", "Message").unwrap());
+        assert_eq!(&png.chunks()[3].chunk_type().to_string(), "ruSt");
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -233,6 +812,235 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_remove_chunk_returns_removed() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        let removed = png.remove_chunk("TeSt").unwrap();
+
+        assert_eq!(removed.chunk_type().to_string(), "TeSt");
+        assert_eq!(removed.data(), b"Message");
+        assert!(png.remove_chunk("TeSt").is_err());
+    }
+
+    #[test]
+    fn test_swap_chunks() {
+        let mut png = testing_png();
+        png.swap_chunks(0, 2).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+
+        assert_eq!(types, ["LASt", "miDl", "FrSt"]);
+        assert!(png.swap_chunks(1, 3).is_err());
+    }
+
+    #[test]
+    fn test_trailing_data_after_iend() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(&chunk_from_strings("ruSt", "after IEND").unwrap().as_bytes());
+        bytes.extend_from_slice(b"trailing junk");
+
+        let options = PngParseOptions {
+            chunks_after_iend: true,
+            ..PngParseOptions::default()
+        };
+        let (png, _) = Png::try_from_opts(bytes.as_slice(), options).unwrap();
+        assert_eq!(png.trailing_data(), b"trailing junk");
+        assert!(png.chunk_by_type("ruSt").is_some());
+        assert_eq!(png.as_bytes(), bytes);
+        assert_eq!(png.total_size(), bytes.len());
+        assert!(Png::try_from(&PNG_FILE[..]).unwrap().trailing_data().is_empty());
+    }
+
+    #[test]
+    fn test_chunk_after_iend_is_trailing_data_by_default() {
+        let after_iend = chunk_from_strings("ruSt", "after IEND").unwrap().as_bytes();
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(&after_iend);
+
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        assert!(png.chunk_by_type("ruSt").is_none());
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+        assert_eq!(png.trailing_data(), after_iend.as_slice());
+        assert_eq!(png.as_bytes(), bytes);
+
+        let (lenient, _) = Png::try_from_lenient(bytes.as_slice()).unwrap();
+        assert!(lenient.chunk_by_type("ruSt").is_none());
+        assert_eq!(lenient.trailing_data(), after_iend.as_slice());
+    }
+
+    #[test]
+    fn test_short_input_is_an_error() {
+        assert!(Png::try_from(&PNG_FILE[..4]).is_err());
+    }
+
+    #[test]
+    fn test_png_builder() {
+        let chunks: Vec<Chunk> = (0..500)
+            .map(|i| chunk_from_strings("ruSt", &format!("message {}", i)).unwrap())
+            .collect();
+        let mut builder = PngBuilder::new();
+        for chunk in &chunks {
+            builder.push_chunk(chunk.clone());
+        }
+        let mut written = Vec::new();
+        builder.write_to(&mut written).unwrap();
+        let expected = Png::from_chunks(chunks);
+
+        assert_eq!(written, expected.as_bytes());
+        assert_eq!(builder.build().as_bytes(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_chunk_count_and_is_empty() {
+        let empty = Png::try_from(&Png::STANDARD_HEADER[..]).unwrap();
+        assert_eq!(empty.chunk_count(), 0);
+        assert!(empty.is_empty());
+
+        let png = testing_png();
+        assert_eq!(png.chunk_count(), 3);
+        assert!(!png.is_empty());
+    }
+
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("ruSt", "Message").unwrap()).unwrap();
+        png.insert_chunk(4, chunk_from_strings("teSt", "Message").unwrap()).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+
+        assert_eq!(types, ["FrSt", "ruSt", "miDl", "LASt", "teSt"]);
+        assert!(png.insert_chunk(6, chunk_from_strings("teSt", "Message").unwrap()).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn test_png_round_trip(chunks in prop::collection::vec(arb_chunk(), 0..16)) {
+            let png = Png::from_chunks(chunks.clone());
+            let parsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+            prop_assert_eq!(parsed.chunks(), chunks.as_slice());
+            prop_assert!(parsed.trailing_data().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_search_finds_hidden_message() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("xYzw", "a secret message").unwrap());
+
+        let matches = png.search(b"secret");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(&matches[0].0.chunk_type().to_string(), "xYzw");
+        assert_eq!(matches[0].1, 2);
+    }
+
+    #[test]
+    fn test_search_no_match() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.search(b"secret message").is_empty());
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+        png.append_chunk(chunk_from_strings("ruSt", "Other message").unwrap());
+        png.append_chunk(chunk_from_strings("teSt", "Message").unwrap());
+
+        assert_eq!(png.dedupe(), 1);
+        assert_eq!(png.chunks_by_type("ruSt").len(), 2);
+        assert_eq!(png.chunks().len(), 6);
+        assert_eq!(png.dedupe(), 0);
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let mut png = Png::from_chunks(
+            ["IDAT", "tEXt", "IEND", "IDAT", "gAMA", "ruSt", "IHDR", "IDAT", "PLTE", "tRNS"]
+                .iter()
+                .enumerate()
+                .map(|(index, chunk_type)| chunk_from_strings(chunk_type, &index.to_string()).unwrap())
+                .collect(),
+        );
+
+        assert_eq!(png.canonicalize(), 10);
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "IDAT", "IDAT", "tEXt", "ruSt", "IEND"]);
+        let idat: Vec<&[u8]> = png.chunks_by_type("IDAT").iter().map(|chunk| chunk.data()).collect();
+        assert_eq!(idat, [b"0", b"3", b"7"]);
+        assert_eq!(png.canonicalize(), 0);
+    }
+
+    #[test]
+    fn test_append_only() {
+        let mut png = testing_png().append_only();
+
+        assert!(png.remove_chunk("FrSt").is_err());
+        assert!(png.insert_chunk(0, chunk_from_strings("ruSt", "Message").unwrap()).is_err());
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+
+        assert_eq!(png.chunk_count(), 4);
+        assert_eq!(png.chunks()[0].chunk_type().to_string(), "FrSt");
+        assert_eq!(png.into_inner().chunks()[3].chunk_type().to_string(), "ruSt");
+    }
+
+    #[test]
+    fn test_append_only_chunk_survives_reparse() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap().append_only();
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+
+        let mut written = Vec::new();
+        png.write_to(&mut written).unwrap();
+        let reparsed = Png::try_from(written.as_slice()).unwrap();
+        assert_eq!(reparsed.chunk_by_type("ruSt").unwrap().data(), b"Message");
+        assert_eq!(reparsed.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+        assert!(reparsed.trailing_data().is_empty());
+    }
+
+    #[test]
+    fn test_canonicalize_keeps_unconstrained_chunks_before_idat() {
+        let chunks = |types: &[&str]| -> Vec<Chunk> {
+            types.iter().map(|chunk_type| chunk_from_strings(chunk_type, "data").unwrap()).collect()
+        };
+        let types = |png: &Png| -> Vec<String> {
+            png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+        };
+
+        let mut png = Png::from_chunks(chunks(&["IHDR", "sCAL", "ruSt", "IDAT", "tEXt", "IEND"]));
+        assert_eq!(png.canonicalize(), 0);
+        assert_eq!(types(&png), ["IHDR", "sCAL", "ruSt", "IDAT", "tEXt", "IEND"]);
+
+        // sCAL has to come before IDAT, ruSt may go anywhere and stays where it was
+        let mut png = Png::from_chunks(chunks(&["IHDR", "ruSt", "IDAT", "sCAL", "teSt", "IEND"]));
+        assert_eq!(png.canonicalize(), 2);
+        assert_eq!(types(&png), ["IHDR", "ruSt", "sCAL", "IDAT", "teSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_strip() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_before_end(chunk_from_strings("ruSt", "Message").unwrap());
+        png.insert_before_end(chunk_from_strings("teSt", "Message").unwrap());
+
+        let ancillary = png.chunks().iter().filter(|chunk| !chunk.chunk_type().is_critical()).count();
+
+        assert_eq!(png.strip(&[ChunkType::from_str("teSt").unwrap()]).len(), ancillary - 1);
+        assert_eq!(png.chunks_by_type("teSt").len(), 1);
+        assert_eq!(png.strip(&[]), [chunk_from_strings("teSt", "Message").unwrap()]);
+        assert!(png.chunks().iter().all(|chunk| chunk.chunk_type().is_critical()));
+    }
+
+    #[test]
+    fn test_open() {
+        let path = std::env::temp_dir().join(format!("png_message_{}_open.png", std::process::id()));
+        fs::write(&path, PNG_FILE).unwrap();
+        let png = Png::open(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(png.unwrap().as_bytes(), PNG_FILE);
+        assert!(Png::open(&path).unwrap_err().to_string().starts_with("Could not read"));
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -243,10 +1051,29 @@ mod tests {
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
-        let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_progress_reported_per_chunk() {
+        let mut reports = Vec::new();
+        let png = Png::try_from_with_progress(&PNG_FILE[..], |parsed, total| {
+            reports.push((parsed, total))
+        })
+        .unwrap();
+
+        assert_eq!(reports.len(), png.chunks().len());
+        assert_eq!(reports.last(), Some(&(PNG_FILE.len(), PNG_FILE.len())));
+    }
+
+    #[test]
+    fn test_total_size() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.total_size(), png.as_bytes().len());
+        assert_eq!(png.total_size(), PNG_FILE.len());
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -0,0 +1,121 @@
+use std::str::FromStr;
+
+use crate::Error;
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// How chunk data is turned into text for display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The data as UTF-8, escaped like `Escape` when it isn't valid UTF-8.
+    Utf8,
+    /// Lowercase hex, two digits per byte.
+    Hex,
+    /// Standard padded base64.
+    Base64,
+    /// UTF-8 text with control characters and invalid bytes shown as `\xNN`.
+    Escape,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utf8" => Ok(Format::Utf8),
+            "hex" => Ok(Format::Hex),
+            "base64" => Ok(Format::Base64),
+            "escape" => Ok(Format::Escape),
+            _ => Err(format!("Unknown format '{}', expected utf8, hex, base64 or escape", s).into()),
+        }
+    }
+}
+
+pub fn render(data: &[u8], format: Format) -> String {
+    match format {
+        Format::Utf8 => match std::str::from_utf8(data) {
+            Ok(text) => text.to_string(),
+            Err(_) => escape(data),
+        },
+        Format::Hex => data.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        Format::Base64 => base64(data),
+        Format::Escape => escape(data),
+    }
+}
+
+fn escape(data: &[u8]) -> String {
+    let mut escaped = String::new();
+    for chunk in data.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                c if c.is_control() => {
+                    let mut bytes = [0; 4];
+                    for byte in c.encode_utf8(&mut bytes).bytes() {
+                        escaped.push_str(&format!("\\x{:02x}", byte));
+                    }
+                }
+                c => escaped.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    escaped
+}
+
+fn base64(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    for group in data.chunks(3) {
+        let bytes = [group[0], *group.get(1).unwrap_or(&0), *group.get(2).unwrap_or(&0)];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= group.len() {
+                encoded.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIXED: &[u8] = b"hi\n\x00\\\xffok";
+
+    #[test]
+    fn test_render_utf8() {
+        assert_eq!(render(b"plain text\n", Format::Utf8), "plain text\n");
+        // invalid UTF-8 falls back to escaping rather than failing
+        assert_eq!(render(MIXED, Format::Utf8), render(MIXED, Format::Escape));
+    }
+
+    #[test]
+    fn test_render_hex() {
+        assert_eq!(render(MIXED, Format::Hex), "68690a005cff6f6b");
+    }
+
+    #[test]
+    fn test_render_base64() {
+        assert_eq!(render(MIXED, Format::Base64), "aGkKAFz/b2s=");
+        assert_eq!(render(b"ab", Format::Base64), "YWI=");
+        assert_eq!(render(b"abc", Format::Base64), "YWJj");
+        assert_eq!(render(b"", Format::Base64), "");
+    }
+
+    #[test]
+    fn test_render_escape() {
+        assert_eq!(render(MIXED, Format::Escape), "hi\\x0a\\x00\\\\\\xffok");
+        assert_eq!(render("héllo".as_bytes(), Format::Escape), "héllo");
+    }
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!(Format::from_str("base64").unwrap(), Format::Base64);
+        assert!(Format::from_str("binary").is_err());
+    }
+}
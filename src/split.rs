@@ -0,0 +1,244 @@
+use std::convert::TryInto;
+
+use crc::crc32;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::Result;
+
+// every piece of a split message starts with a small header so the pieces can be
+// put back together in order: magic, message id, sequence number, total piece count
+// and a checksum of the piece. The message id is the CRC-32 of the whole message, so
+// pieces of different messages under the same chunk type are told apart, and the
+// piece checksum keeps ordinary data that happens to start with the magic from
+// being read as a piece
+const MAGIC: [u8; 4] = *b"SPLT";
+const HEADER_LENGTH: usize = 16;
+
+// the fields of a piece header, see above
+struct Piece<'a> {
+    id: u32,
+    index: u16,
+    count: u16,
+    data: &'a [u8],
+}
+
+impl<'a> Piece<'a> {
+    fn parse(chunk: &'a Chunk) -> Option<Self> {
+        let data = chunk.data();
+        if data.len() < HEADER_LENGTH || data[..4] != MAGIC {
+            return None;
+        }
+        let checksum = u32::from_be_bytes([data[12], data[13], data[14], data[15]]);
+        if piece_checksum(&data[4..12], &data[HEADER_LENGTH..]) != checksum {
+            return None;
+        }
+        Some(Piece {
+            id: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            index: u16::from_be_bytes([data[8], data[9]]),
+            count: u16::from_be_bytes([data[10], data[11]]),
+            data: &data[HEADER_LENGTH..],
+        })
+    }
+}
+
+fn piece_checksum(fields: &[u8], piece: &[u8]) -> u32 {
+    crc32::checksum_ieee(&[fields, piece].concat())
+}
+
+// split 'message' into chunks of 'chunk_type' carrying at most 'size' bytes of message each
+pub fn split_message(chunk_type: ChunkType, message: &[u8], size: usize) -> Result<Vec<Chunk>> {
+    if size == 0 {
+        return Err("Split size must be greater than zero".into());
+    }
+
+    let pieces: Vec<&[u8]> = if message.is_empty() {
+        vec![message]
+    } else {
+        message.chunks(size).collect()
+    };
+    split_pieces(chunk_type, &pieces)
+}
+
+// store each of 'pieces' in its own chunk of 'chunk_type', in order
+pub fn split_pieces(chunk_type: ChunkType, pieces: &[&[u8]]) -> Result<Vec<Chunk>> {
+    let count: u16 = pieces
+        .len()
+        .try_into()
+        .map_err(|_| "Message splits into too many pieces")?;
+    let id = crc32::checksum_ieee(&pieces.concat());
+
+    Ok(pieces
+        .iter()
+        .enumerate()
+        .map(|(index, piece)| {
+            let fields: Vec<u8> = id
+                .to_be_bytes()
+                .iter()
+                .chain((index as u16).to_be_bytes().iter())
+                .chain(count.to_be_bytes().iter())
+                .copied()
+                .collect();
+            let data = MAGIC
+                .iter()
+                .chain(fields.iter())
+                .chain(piece_checksum(&fields, piece).to_be_bytes().iter())
+                .chain(piece.iter())
+                .copied()
+                .collect();
+            Chunk::new(chunk_type, data)
+        })
+        .collect())
+}
+
+// the messages held by 'chunks' in file order: a split message counts once, where its
+// first piece is, and every other chunk is a message of its own
+pub fn join_messages(chunks: &[&Chunk]) -> Result<Vec<Vec<u8>>> {
+    let mut messages: Vec<Message> = Vec::new();
+    for chunk in chunks {
+        match Piece::parse(chunk) {
+            Some(piece) => match messages.iter_mut().find(|message| message.id() == Some(piece.id)) {
+                Some(Message::Split(_, pieces)) => pieces.push(piece),
+                _ => messages.push(Message::Split(piece.id, vec![piece])),
+            },
+            None => messages.push(Message::Plain(chunk.data())),
+        }
+    }
+
+    messages
+        .into_iter()
+        .map(|message| match message {
+            Message::Plain(data) => Ok(data.to_vec()),
+            Message::Split(id, pieces) => join_pieces(id, pieces),
+        })
+        .collect()
+}
+
+// reassemble a single split message, returns None when the chunks were not written by
+// 'split_message'
+pub fn join_chunks(chunks: &[&Chunk]) -> Result<Option<Vec<u8>>> {
+    let pieces: Option<Vec<Piece>> = chunks.iter().map(|chunk| Piece::parse(chunk)).collect();
+    let pieces = match pieces {
+        Some(pieces) if !pieces.is_empty() => pieces,
+        _ => return Ok(None),
+    };
+
+    let id = pieces[0].id;
+    if pieces.iter().any(|piece| piece.id != id) {
+        return Err("Chunks hold pieces of more than one split message".into());
+    }
+    join_pieces(id, pieces).map(Some)
+}
+
+enum Message<'a> {
+    Plain(&'a [u8]),
+    Split(u32, Vec<Piece<'a>>),
+}
+
+impl Message<'_> {
+    fn id(&self) -> Option<u32> {
+        match self {
+            Message::Plain(_) => None,
+            Message::Split(id, _) => Some(*id),
+        }
+    }
+}
+
+fn join_pieces(id: u32, mut pieces: Vec<Piece>) -> Result<Vec<u8>> {
+    pieces.sort_by_key(|piece| piece.index);
+    if let Some(pair) = pieces.windows(2).find(|pair| pair[0].index == pair[1].index) {
+        return Err(format!("Split message {:08x} has piece {} more than once", id, pair[0].index).into());
+    }
+
+    let count = pieces[0].count;
+    if pieces.len() != count as usize
+        || pieces
+            .iter()
+            .enumerate()
+            .any(|(expected, piece)| piece.index as usize != expected || piece.count != count)
+    {
+        return Err(format!("Split message {:08x} is missing pieces", id).into());
+    }
+
+    let message: Vec<u8> = pieces.iter().flat_map(|piece| piece.data.iter().copied()).collect();
+    if crc32::checksum_ieee(&message) != id {
+        return Err(format!("Split message {:08x} does not match its checksum", id).into());
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_split_into_three_and_join() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let message = b"This message is split into three";
+        let chunks = split_message(chunk_type, message, 12).unwrap();
+        assert_eq!(chunks.len(), 3);
+
+        let mut shuffled: Vec<&Chunk> = chunks.iter().collect();
+        shuffled.reverse();
+        let joined = join_chunks(&shuffled).unwrap().unwrap();
+        assert_eq!(joined, message.to_vec());
+    }
+
+    #[test]
+    fn test_join_missing_piece() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let chunks = split_message(chunk_type, b"This message is split into three", 12).unwrap();
+        let partial: Vec<&Chunk> = chunks.iter().skip(1).collect();
+        assert!(join_chunks(&partial).is_err());
+    }
+
+    #[test]
+    fn test_join_unsplit_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"plain".to_vec());
+        assert!(join_chunks(&[&chunk]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_plain_message_with_magic_is_not_split() {
+        let data = b"SPLT is how this message happens to start".to_vec();
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), data.clone());
+        assert!(join_chunks(&[&chunk]).unwrap().is_none());
+        assert_eq!(join_messages(&[&chunk]).unwrap(), vec![data]);
+    }
+
+    #[test]
+    fn test_join_two_split_messages() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let first = split_message(chunk_type, b"the first split message", 5).unwrap();
+        let second = split_message(chunk_type, b"and the second one", 5).unwrap();
+        let plain = Chunk::new(chunk_type, b"plain".to_vec());
+        let chunks: Vec<&Chunk> = first.iter().chain(std::iter::once(&plain)).chain(second.iter()).collect();
+
+        let messages = join_messages(&chunks).unwrap();
+        assert_eq!(
+            messages,
+            vec![
+                b"the first split message".to_vec(),
+                b"plain".to_vec(),
+                b"and the second one".to_vec()
+            ]
+        );
+        assert!(join_chunks(&chunks).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_join_duplicate_piece() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let chunks = split_message(chunk_type, b"This message is split into three", 12).unwrap();
+        let duplicated: Vec<&Chunk> = chunks.iter().chain(chunks.iter().take(1)).collect();
+        let err = join_chunks(&duplicated).unwrap_err();
+        assert!(err.to_string().contains("has piece 0 more than once"));
+    }
+
+    #[test]
+    fn test_split_zero_size() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        assert!(split_message(chunk_type, b"message", 0).is_err());
+    }
+}
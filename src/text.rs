@@ -0,0 +1,273 @@
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::Result;
+
+// tEXt and zTXt chunks hold a Latin-1 keyword and Latin-1 text separated by a NUL byte,
+// zTXt adds a compression method byte after the separator and deflates the text
+// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.Anc-text
+#[derive(Debug, PartialEq, Eq)]
+pub struct TextChunk {
+    pub keyword: String,
+    pub text: String,
+}
+
+impl TextChunk {
+    const COMPRESSION_DEFLATE: u8 = 0;
+
+    pub fn new(keyword: &str, text: &str) -> Result<Self> {
+        check_keyword(keyword)?;
+        latin1_bytes(text)?;
+
+        Ok(Self {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    // the fields are public, so text set after 'new' may no longer be Latin-1
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let data = [latin1_bytes(&self.keyword)?, vec![0], latin1_bytes(&self.text)?].concat();
+        Ok(Chunk::new(ChunkType::from_str("tEXt").unwrap(), data))
+    }
+
+    pub fn to_compressed_chunk(&self) -> Result<Chunk> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&latin1_bytes(&self.text)?)?;
+        let data = [
+            latin1_bytes(&self.keyword)?,
+            vec![0, TextChunk::COMPRESSION_DEFLATE],
+            encoder.finish()?,
+        ]
+        .concat();
+        Ok(Chunk::new(ChunkType::from_str("zTXt").unwrap(), data))
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = crate::Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = chunk.data();
+        let separator = data
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or("Text chunk has no keyword separator")?;
+        let keyword = latin1_string(&data[..separator]);
+
+        let text = match &chunk.chunk_type().to_string()[..] {
+            "tEXt" => data[separator + 1..].to_vec(),
+            "zTXt" => {
+                match data.get(separator + 1) {
+                    Some(&TextChunk::COMPRESSION_DEFLATE) => {}
+                    _ => return Err("Unknown zTXt compression method".into()),
+                }
+                let mut text = Vec::new();
+                ZlibDecoder::new(&data[separator + 2..]).read_to_end(&mut text)?;
+                text
+            }
+            other => return Err(format!("{} is not a text chunk", other).into()),
+        };
+
+        Ok(Self {
+            keyword,
+            text: latin1_string(&text),
+        })
+    }
+}
+
+// iTXt chunks hold a Latin-1 keyword, a compression flag and method, an ASCII language tag,
+// the keyword translated into that language and UTF-8 text, with NUL separators
+// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.iTXt
+#[derive(Debug, PartialEq, Eq)]
+pub struct InternationalTextChunk {
+    pub keyword: String,
+    /// RFC 1766 language tag such as `en` or `ja-JP`, empty when unknown.
+    pub language: String,
+    pub translated_keyword: String,
+    pub text: String,
+}
+
+impl InternationalTextChunk {
+    pub fn new(keyword: &str, language: &str, translated_keyword: &str, text: &str) -> Result<Self> {
+        check_keyword(keyword)?;
+        if !language.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err("Language tag must be ASCII letters, digits and hyphens".into());
+        }
+        if translated_keyword.contains('\0') {
+            return Err("Translated keyword cannot contain NUL".into());
+        }
+
+        Ok(Self {
+            keyword: keyword.to_string(),
+            language: language.to_string(),
+            translated_keyword: translated_keyword.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    pub fn to_chunk(&self, compressed: bool) -> Result<Chunk> {
+        let text = if compressed {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(self.text.as_bytes())?;
+            encoder.finish()?
+        } else {
+            self.text.as_bytes().to_vec()
+        };
+        let data = [
+            latin1_bytes(&self.keyword)?,
+            vec![0, compressed as u8, TextChunk::COMPRESSION_DEFLATE],
+            self.language.as_bytes().to_vec(),
+            vec![0],
+            self.translated_keyword.as_bytes().to_vec(),
+            vec![0],
+            text,
+        ]
+        .concat();
+        Ok(Chunk::new(ChunkType::from_str("iTXt").unwrap(), data))
+    }
+}
+
+impl TryFrom<&Chunk> for InternationalTextChunk {
+    type Error = crate::Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().to_string() != "iTXt" {
+            return Err(format!("{} is not an iTXt chunk", chunk.chunk_type()).into());
+        }
+        let mut fields = chunk.data().splitn(2, |&byte| byte == 0);
+        let keyword = latin1_string(fields.next().unwrap_or_default());
+        let rest = fields.next().ok_or("iTXt chunk has no keyword separator")?;
+        let (compressed, method, rest) = match rest {
+            [compressed, method, rest @ ..] => (*compressed, *method, rest),
+            _ => return Err("iTXt chunk is missing its compression fields".into()),
+        };
+        let mut fields = rest.splitn(3, |&byte| byte == 0);
+        let language = std::str::from_utf8(fields.next().unwrap_or_default())?.to_string();
+        let translated_keyword = std::str::from_utf8(fields.next().ok_or("iTXt chunk has no language separator")?)?;
+        let text = fields.next().ok_or("iTXt chunk has no translated keyword separator")?;
+
+        let text = match (compressed, method) {
+            (0, _) => text.to_vec(),
+            (1, TextChunk::COMPRESSION_DEFLATE) => {
+                let mut inflated = Vec::new();
+                ZlibDecoder::new(text).read_to_end(&mut inflated)?;
+                inflated
+            }
+            _ => return Err("Unknown iTXt compression method".into()),
+        };
+
+        Ok(Self {
+            keyword,
+            language,
+            translated_keyword: translated_keyword.to_string(),
+            text: String::from_utf8(text)?,
+        })
+    }
+}
+
+fn check_keyword(keyword: &str) -> Result<()> {
+    if keyword.is_empty() || keyword.len() > 79 {
+        return Err("Keyword must be 1 to 79 characters long".into());
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err("Keyword cannot have leading, trailing or consecutive spaces".into());
+    }
+    if keyword.chars().any(|c| !(' '..='~').contains(&c) && !('\u{a1}'..='\u{ff}').contains(&c)) {
+        return Err("Keyword must be printable Latin-1".into());
+    }
+    Ok(())
+}
+
+fn latin1_bytes(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|c| u8::try_from(c as u32).map_err(|_| format!("'{}' cannot be stored as Latin-1", c).into()))
+        .collect()
+}
+
+fn latin1_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_chunk_layout() {
+        let chunk = TextChunk::new("Comment", "hidden").unwrap().to_chunk().unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "tEXt");
+        assert_eq!(chunk.data(), b"Comment\0hidden");
+    }
+
+    #[test]
+    fn test_text_chunk_round_trip() {
+        let text = TextChunk::new("Author", "caf\u{e9}").unwrap();
+        let chunk = text.to_chunk().unwrap();
+        assert_eq!(chunk.data(), b"Author\0caf\xe9");
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+    }
+
+    #[test]
+    fn test_text_chunk_with_non_latin1_field_is_an_error() {
+        let mut text = TextChunk::new("Comment", "hidden").unwrap();
+        text.text = "\u{3053}\u{3093}".to_string();
+        assert!(text.to_chunk().is_err());
+        assert!(text.to_compressed_chunk().is_err());
+    }
+
+    #[test]
+    fn test_compressed_text_chunk_round_trip() {
+        let text = TextChunk::new("Comment", &"hidden ".repeat(20)).unwrap();
+        let chunk = text.to_compressed_chunk().unwrap();
+
+        assert_eq!(&chunk.chunk_type().to_string(), "zTXt");
+        assert_eq!(&chunk.data()[..9], b"Comment\0\0");
+        assert!(chunk.data().len() < 140);
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+    }
+
+    #[test]
+    fn test_invalid_keyword() {
+        assert!(TextChunk::new("", "text").is_err());
+        assert!(TextChunk::new(" Comment", "text").is_err());
+        assert!(TextChunk::new(&"k".repeat(80), "text").is_err());
+        assert!(TextChunk::new("Comment", "\u{263a}").is_err());
+    }
+
+    #[test]
+    fn test_international_text_chunk_round_trip() {
+        let text = InternationalTextChunk::new("Title", "ja", "\u{984c}\u{540d}", "\u{3053}\u{3093}\u{306b}\u{3061}\u{306f}").unwrap();
+        let chunk = text.to_chunk(false).unwrap();
+
+        assert_eq!(&chunk.chunk_type().to_string(), "iTXt");
+        assert_eq!(&chunk.data()[..11], b"Title\0\0\0ja\0");
+        assert_eq!(InternationalTextChunk::try_from(&chunk).unwrap(), text);
+
+        let compressed = text.to_chunk(true).unwrap();
+        assert_eq!(compressed.data()[6], 1);
+        assert_eq!(InternationalTextChunk::try_from(&compressed).unwrap(), text);
+    }
+
+    #[test]
+    fn test_invalid_international_text_chunk() {
+        assert!(InternationalTextChunk::new("Title", "en US", "", "text").is_err());
+        let truncated = Chunk::new(ChunkType::from_str("iTXt").unwrap(), b"Title\0\0".to_vec());
+        assert!(InternationalTextChunk::try_from(&truncated).is_err());
+        let text = TextChunk::new("Title", "text").unwrap().to_chunk().unwrap();
+        assert!(InternationalTextChunk::try_from(&text).is_err());
+    }
+
+    #[test]
+    fn test_not_a_text_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"key\0value".to_vec());
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+}
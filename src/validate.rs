@@ -0,0 +1,134 @@
+use std::convert::TryFrom;
+
+use crate::ihdr::Ihdr;
+use crate::png::Png;
+
+/// A single problem found by `validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    /// Short machine-readable name of the check that failed, e.g. `crc` or `missing_iend`.
+    pub kind: &'static str,
+    pub detail: String,
+}
+
+impl Failure {
+    fn new(kind: &'static str, detail: impl Into<String>) -> Self {
+        Failure {
+            kind,
+            detail: detail.into(),
+        }
+    }
+}
+
+// check the structure of a PNG file: that it parses, every CRC matches, and the
+// IHDR, IDAT and IEND chunks are where the spec requires them
+pub fn validate(bytes: &[u8]) -> Vec<Failure> {
+    let png = match Png::try_from_lenient(bytes) {
+        Ok((png, _)) => png,
+        Err(err) => return vec![Failure::new("parse", err.to_string())],
+    };
+
+    let mut failures = Vec::new();
+    for (chunk, offset) in png.chunks().iter().zip(png.chunk_offsets()) {
+        if !chunk.has_valid_crc() {
+            failures.push(Failure::new(
+                "crc",
+                format!(
+                    "{} chunk at offset {} has CRC {:08x}, expected {:08x}",
+                    chunk.chunk_type(),
+                    offset,
+                    chunk.crc(),
+                    chunk.computed_crc()
+                ),
+            ));
+        }
+    }
+
+    let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+    match png.chunks().first() {
+        Some(first) if types[0] == "IHDR" => {
+            if let Err(err) = Ihdr::try_from(first) {
+                failures.push(Failure::new("invalid_ihdr", err.to_string()));
+            }
+        }
+        _ => failures.push(Failure::new("missing_ihdr", "The first chunk is not IHDR")),
+    }
+    if !types.iter().any(|chunk_type| chunk_type == "IDAT") {
+        failures.push(Failure::new("missing_idat", "There is no IDAT chunk"));
+    }
+    if types.last().map(String::as_str) != Some("IEND") {
+        failures.push(Failure::new("missing_iend", "The last chunk is not IEND"));
+    }
+    if !png.trailing_data().is_empty() {
+        failures.push(Failure::new(
+            "trailing_data",
+            format!("{} bytes of trailing data after IEND", png.trailing_data().len()),
+        ));
+    }
+
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    // signature, 1x1 RGBA IHDR, an empty IDAT and IEND
+    fn testing_png() -> Vec<u8> {
+        let ihdr = [0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0];
+        let chunks = [("IHDR", &ihdr[..]), ("IDAT", &[]), ("IEND", &[])];
+        [137, 80, 78, 71, 13, 10, 26, 10]
+            .iter()
+            .copied()
+            .chain(chunks.iter().flat_map(|(chunk_type, data)| {
+                Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).as_bytes()
+            }))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_png() {
+        assert_eq!(validate(&testing_png()), []);
+    }
+
+    #[test]
+    fn test_missing_iend() {
+        let bytes = testing_png();
+        let failures = validate(&bytes[..bytes.len() - 12]);
+        let kinds: Vec<&str> = failures.iter().map(|failure| failure.kind).collect();
+        assert_eq!(kinds, ["missing_iend"]);
+    }
+
+    #[test]
+    fn test_crc_mismatch() {
+        let mut bytes = testing_png();
+        // last byte of the IHDR CRC
+        bytes[32] ^= 0xff;
+        let failures = validate(&bytes);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].kind, "crc");
+        assert!(failures[0].detail.starts_with("IHDR chunk at offset 8"));
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = testing_png();
+        bytes.extend_from_slice(b"junk");
+        assert_eq!(validate(&bytes), [Failure::new("trailing_data", "4 bytes of trailing data after IEND")]);
+    }
+
+    #[test]
+    fn test_chunk_after_iend_is_trailing_data() {
+        let mut bytes = testing_png();
+        bytes.extend_from_slice(&Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hidden".to_vec()).as_bytes());
+        assert_eq!(validate(&bytes), [Failure::new("trailing_data", "18 bytes of trailing data after IEND")]);
+    }
+
+    #[test]
+    fn test_not_a_png() {
+        assert_eq!(validate(b"definitely not a png")[0].kind, "parse");
+    }
+}
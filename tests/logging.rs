@@ -0,0 +1,32 @@
+use std::process::Command;
+use std::str::FromStr;
+
+use png_message::chunk::Chunk;
+use png_message::chunk_type::ChunkType;
+use png_message::png::PngBuilder;
+
+#[test]
+fn test_parse_logs_debug_traces() {
+    let ihdr = [0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0];
+    let mut builder = PngBuilder::new();
+    builder
+        .push_chunk(Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr.to_vec()))
+        .push_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hi".to_vec()))
+        .push_chunk(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()));
+    let path = std::env::temp_dir().join(format!("png_message_{}_logging.png", std::process::id()));
+    std::fs::write(&path, builder.build().as_bytes()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_png_message"))
+        .args(["decode", path.to_str().unwrap(), "ruSt"])
+        .env("RUST_LOG", "debug")
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Parsed IHDR chunk at offset 8"));
+    assert!(stderr.contains("Parsed ruSt chunk at offset 33"));
+    assert!(stderr.contains("Parsed IEND chunk at offset 47"));
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("Parsed"));
+}
@@ -0,0 +1,52 @@
+use std::convert::TryFrom;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::str::FromStr;
+
+use png_message::chunk::Chunk;
+use png_message::chunk_type::ChunkType;
+use png_message::png::{Png, PngBuilder};
+
+fn fixture() -> Vec<u8> {
+    let ihdr = [0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0];
+    let mut builder = PngBuilder::new();
+    builder
+        .push_chunk(Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr.to_vec()))
+        .push_chunk(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()));
+    builder.build().as_bytes()
+}
+
+// run the binary with 'stdin' piped in, like `cat image.png | png_message ...`
+fn run(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_png_message"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+#[test]
+fn test_encode_and_decode_through_pipes() {
+    let encoded = run(&["encode", "-", "ruSt", "hi", "-o", "-"], &fixture()).stdout;
+    let png = Png::try_from(encoded.as_slice()).unwrap();
+    assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"hi");
+
+    let decoded = run(&["decode", "-", "ruSt", "--raw"], &encoded).stdout;
+    assert_eq!(decoded, b"hi");
+}
+
+#[test]
+fn test_encode_replace_through_pipes() {
+    let first = run(&["encode", "-", "ruSt", "first", "-o", "-"], &fixture()).stdout;
+    let replaced = run(&["encode", "-", "ruSt", "second", "--replace", "-o", "-"], &first);
+    assert!(String::from_utf8_lossy(&replaced.stderr).contains("Replaced the existing ruSt chunk"));
+
+    let decoded = run(&["decode", "-", "ruSt", "--raw", "--all"], &replaced.stdout).stdout;
+    assert_eq!(decoded, b"second");
+}